@@ -0,0 +1,420 @@
+use crate::simulation::Simulation;
+use crate::stats::LatencyStats;
+use crate::timing::Seconds;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+
+/// The query rate offered to the cluster over time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TrafficProfile {
+    Constant {
+        queries_per_second: f64,
+    },
+    /// A sinusoidal day/night cycle starting at its trough.
+    Diurnal {
+        min_qps: f64,
+        max_qps: f64,
+        period: Seconds,
+    },
+    /// A constant base rate with a burst of `spike_qps` starting at `start`.
+    Spike {
+        base_qps: f64,
+        spike_qps: f64,
+        start: Seconds,
+        duration: Seconds,
+    },
+}
+
+/// The signal an autoscaler reacts to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScalingPolicy {
+    /// Adds a node when the utilization of the last interval exceeds `scale_up` and
+    /// removes one when it drops below `scale_down`.
+    Utilization { scale_up: f64, scale_down: f64 },
+    /// Adds a node when the p99 latency of the last interval exceeds `scale_up` times
+    /// the SLO and removes one when it drops below `scale_down` times the SLO.
+    Latency { scale_up: f64, scale_down: f64 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Autoscaler {
+    pub policy: ScalingPolicy,
+    /// The p99 latency every evaluation interval is expected to meet.
+    pub latency_slo: Seconds,
+    pub min_nodes: usize,
+    pub max_nodes: usize,
+    /// The interval at which the autoscaler observes the cluster.
+    pub evaluation_interval: Seconds,
+    /// The minimum time between two scaling actions.
+    pub cooldown: Seconds,
+    /// The time it takes an added node to start serving queries.
+    pub provisioning_delay: Seconds,
+}
+
+/// The state of the cluster over one evaluation interval.
+#[derive(Debug, Clone)]
+pub struct AutoscaleSample {
+    /// The end of the interval.
+    pub time: Seconds,
+    pub offered_qps: f64,
+    /// The nodes serving queries at the end of the interval.
+    pub active_nodes: usize,
+    /// The nodes being provisioned at the end of the interval.
+    pub pending_nodes: usize,
+    pub latency: LatencyStats,
+    pub utilization: f64,
+    pub slo_violated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoscaleTimeline {
+    pub samples: Vec<AutoscaleSample>,
+    /// The number of intervals whose p99 latency exceeded the SLO.
+    pub slo_violations: usize,
+    /// The capacity paid for, including nodes being provisioned.
+    pub node_hours: f64,
+}
+
+impl TrafficProfile {
+    pub fn rate(&self, time: Seconds) -> f64 {
+        match *self {
+            TrafficProfile::Constant { queries_per_second } => queries_per_second,
+            TrafficProfile::Diurnal {
+                min_qps,
+                max_qps,
+                period,
+            } => {
+                let phase = (1. - (2. * PI * *time / *period).cos()) / 2.;
+                min_qps + (max_qps - min_qps) * phase
+            }
+            TrafficProfile::Spike {
+                base_qps,
+                spike_qps,
+                start,
+                duration,
+            } => {
+                if *time >= *start && *time < *start + *duration {
+                    spike_qps
+                } else {
+                    base_qps
+                }
+            }
+        }
+    }
+
+    fn max_rate(&self) -> f64 {
+        match *self {
+            TrafficProfile::Constant { queries_per_second } => queries_per_second,
+            TrafficProfile::Diurnal {
+                min_qps, max_qps, ..
+            } => min_qps.max(max_qps),
+            TrafficProfile::Spike {
+                base_qps,
+                spike_qps,
+                ..
+            } => base_qps.max(spike_qps),
+        }
+    }
+}
+
+impl Autoscaler {
+    /// The change in node count the policy asks for after an interval.
+    fn decide(&self, latency: &LatencyStats, utilization: f64) -> isize {
+        let (up, down) = match self.policy {
+            ScalingPolicy::Utilization {
+                scale_up,
+                scale_down,
+            } => (utilization > scale_up, utilization < scale_down),
+            ScalingPolicy::Latency {
+                scale_up,
+                scale_down,
+            } => (
+                *latency.p99 > *self.latency_slo * scale_up,
+                *latency.p99 < *self.latency_slo * scale_down,
+            ),
+        };
+        if up {
+            1
+        } else if down {
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+impl Simulation {
+    /// Replays a traffic profile against a cluster whose size is controlled by an
+    /// autoscaler.
+    ///
+    /// Every query occupies one node for the duration of [`Simulation::simulate_find`]
+    /// and queries wait in a single FIFO queue.
+    /// The cluster starts with the simulation's node count.
+    ///
+    /// Since any node can serve a whole query, every node acts as a full replica of the
+    /// index: adding a node adds a replica, and there is no separate replica count to
+    /// scale. Scaling the replicas of individual shards would require a per-shard queueing
+    /// model, which this simulation does not have.
+    pub fn simulate_autoscaling<D>(
+        &self,
+        index_id: usize,
+        traffic: &TrafficProfile,
+        autoscaler: &Autoscaler,
+        duration: D,
+        seed: u64,
+    ) -> AutoscaleTimeline
+    where
+        D: Into<Seconds>,
+    {
+        assert!(autoscaler.min_nodes > 0 && autoscaler.min_nodes <= autoscaler.max_nodes);
+        let horizon = *duration.into();
+        let interval = *autoscaler.evaluation_interval;
+        assert!(interval > 0.);
+        let service = *self.simulate_find(index_id).duration;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let initial = self
+            .num_nodes
+            .clamp(autoscaler.min_nodes, autoscaler.max_nodes);
+        let mut node_free_at = vec![0f64; initial];
+        let mut pending: Vec<f64> = Vec::new();
+        let mut last_action = f64::NEG_INFINITY;
+        let mut node_seconds = 0.;
+
+        let mut timeline = AutoscaleTimeline {
+            samples: Vec::new(),
+            slo_violations: 0,
+            node_hours: 0.,
+        };
+        let mut window_latencies = Vec::new();
+        let mut window_busy = 0.;
+
+        // Arrivals of the non-homogeneous Poisson process are generated by thinning.
+        let max_rate = traffic.max_rate();
+        let advance = |rng: &mut StdRng, time: f64| -> f64 {
+            let mut time = time;
+            loop {
+                time += -(1. - rng.gen::<f64>()).ln() / max_rate;
+                if time > horizon {
+                    return f64::INFINITY;
+                }
+                if rng.gen::<f64>() * max_rate <= traffic.rate(Seconds(time)) {
+                    return time;
+                }
+            }
+        };
+        let mut next_arrival = if max_rate > 0. {
+            advance(&mut rng, 0.)
+        } else {
+            f64::INFINITY
+        };
+
+        let mut tick = interval;
+        while tick <= horizon + 1e-9 {
+            while next_arrival < tick {
+                let arrival = next_arrival;
+                // Nodes whose provisioning completed join the cluster.
+                pending.retain(|&ready| {
+                    if ready <= arrival {
+                        node_free_at.push(ready);
+                        false
+                    } else {
+                        true
+                    }
+                });
+
+                let (node, free_at) = node_free_at
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap();
+                let finish = free_at.max(arrival) + service;
+                node_free_at[node] = finish;
+                window_latencies.push(Seconds(finish - arrival));
+                window_busy += service;
+                next_arrival = advance(&mut rng, arrival);
+            }
+
+            pending.retain(|&ready| {
+                if ready <= tick {
+                    node_free_at.push(ready);
+                    false
+                } else {
+                    true
+                }
+            });
+
+            let latency = LatencyStats::from_samples(&window_latencies);
+            let utilization = window_busy / (interval * node_free_at.len() as f64);
+            let slo_violated = latency.count > 0 && latency.p99 > autoscaler.latency_slo;
+            timeline.slo_violations += usize::from(slo_violated);
+            node_seconds += interval * (node_free_at.len() + pending.len()) as f64;
+
+            let capacity = node_free_at.len() + pending.len();
+            if tick - last_action >= *autoscaler.cooldown {
+                match autoscaler.decide(&latency, utilization) {
+                    1 if capacity < autoscaler.max_nodes => {
+                        pending.push(tick + *autoscaler.provisioning_delay);
+                        last_action = tick;
+                    }
+                    -1 if capacity > autoscaler.min_nodes => {
+                        if pending.pop().is_none() {
+                            // Drain the node that frees up first; its queued work completes.
+                            let (node, _) = node_free_at
+                                .iter()
+                                .enumerate()
+                                .min_by(|a, b| a.1.total_cmp(b.1))
+                                .unwrap();
+                            node_free_at.remove(node);
+                        }
+                        last_action = tick;
+                    }
+                    _ => {}
+                }
+            }
+
+            timeline.samples.push(AutoscaleSample {
+                time: Seconds(tick),
+                offered_qps: traffic.rate(Seconds(tick)),
+                active_nodes: node_free_at.len(),
+                pending_nodes: pending.len(),
+                latency,
+                utilization,
+                slo_violated,
+            });
+            window_latencies.clear();
+            window_busy = 0.;
+            tick += interval;
+        }
+
+        timeline.node_hours = node_seconds / 3600.;
+        timeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        // A query occupies a node for 10ms.
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000], 50))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_nodes(2)
+            .build()
+    }
+
+    fn autoscaler(policy: ScalingPolicy) -> Autoscaler {
+        Autoscaler {
+            policy,
+            latency_slo: Milliseconds(50.).into(),
+            min_nodes: 1,
+            max_nodes: 20,
+            evaluation_interval: Seconds(10.),
+            cooldown: Seconds(30.),
+            provisioning_delay: Seconds(20.),
+        }
+    }
+
+    #[test]
+    fn traffic_profiles() {
+        let diurnal = TrafficProfile::Diurnal {
+            min_qps: 10.,
+            max_qps: 110.,
+            period: Seconds(100.),
+        };
+        assert!((diurnal.rate(Seconds(0.)) - 10.).abs() < 1e-9);
+        assert!((diurnal.rate(Seconds(50.)) - 110.).abs() < 1e-9);
+
+        let spike = TrafficProfile::Spike {
+            base_qps: 10.,
+            spike_qps: 500.,
+            start: Seconds(60.),
+            duration: Seconds(30.),
+        };
+        assert_eq!(spike.rate(Seconds(59.)), 10.);
+        assert_eq!(spike.rate(Seconds(75.)), 500.);
+        assert_eq!(spike.rate(Seconds(90.)), 10.);
+    }
+
+    #[test]
+    fn autoscaler_follows_diurnal_traffic() {
+        let traffic = TrafficProfile::Diurnal {
+            min_qps: 20.,
+            max_qps: 400.,
+            period: Seconds(1200.),
+        };
+        let policy = ScalingPolicy::Utilization {
+            scale_up: 0.7,
+            scale_down: 0.3,
+        };
+        let timeline =
+            simulation().simulate_autoscaling(0, &traffic, &autoscaler(policy), Seconds(1200.), 1);
+
+        assert_eq!(timeline.samples.len(), 120);
+        let peak = timeline
+            .samples
+            .iter()
+            .map(|s| s.active_nodes)
+            .max()
+            .unwrap();
+        let last = timeline.samples.last().unwrap().active_nodes;
+        assert!(peak >= 5);
+        assert!(last < peak);
+        assert!(timeline.node_hours < 20. * 1200. / 3600.);
+    }
+
+    #[test]
+    fn traffic_may_stop_after_a_spike() {
+        let traffic = TrafficProfile::Spike {
+            base_qps: 0.,
+            spike_qps: 100.,
+            start: Seconds(10.),
+            duration: Seconds(10.),
+        };
+        let policy = ScalingPolicy::Utilization {
+            scale_up: 0.7,
+            scale_down: 0.3,
+        };
+        let timeline =
+            simulation().simulate_autoscaling(0, &traffic, &autoscaler(policy), Seconds(60.), 1);
+        assert_eq!(timeline.samples.len(), 6);
+        assert_eq!(timeline.samples[5].latency.count, 0);
+    }
+
+    #[test]
+    fn provisioning_delay_causes_violations_during_spikes() {
+        let traffic = TrafficProfile::Spike {
+            base_qps: 50.,
+            spike_qps: 400.,
+            start: Seconds(300.),
+            duration: Seconds(300.),
+        };
+        let policy = ScalingPolicy::Latency {
+            scale_up: 1.,
+            scale_down: 0.5,
+        };
+        let fast = Autoscaler {
+            provisioning_delay: Seconds(0.),
+            cooldown: Seconds(10.),
+            ..autoscaler(policy)
+        };
+        let slow = Autoscaler {
+            provisioning_delay: Seconds(60.),
+            ..autoscaler(policy)
+        };
+
+        let simulation = simulation();
+        let fast = simulation.simulate_autoscaling(0, &traffic, &fast, Seconds(900.), 2);
+        let slow = simulation.simulate_autoscaling(0, &traffic, &slow, Seconds(900.), 2);
+        assert!(slow.slo_violations > fast.slo_violations);
+        assert!(fast.samples[59].active_nodes >= 4);
+        assert!(slow.samples.iter().any(|s| s.pending_nodes > 0));
+    }
+}
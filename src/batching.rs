@@ -0,0 +1,256 @@
+use crate::simulation::Simulation;
+use crate::timing::Seconds;
+
+/// Collects queries into a batch until either the batch is full or the first query
+/// waited for `max_wait`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BatchingPolicy {
+    pub max_batch_size: usize,
+    pub max_wait: Seconds,
+}
+
+/// How the search cost of a batch grows with its size.
+///
+/// A fraction of the cost of a single query is spent streaming the vectors from memory.
+/// In a batch this scan is shared by all queries, leaving only the compute to grow
+/// with the batch size.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BatchCostModel {
+    pub memory_bound_fraction: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BatchingConfig {
+    pub coordinator: BatchingPolicy,
+    pub shard: BatchingPolicy,
+    pub cost: BatchCostModel,
+}
+
+#[derive(Debug)]
+pub struct BatchingResult {
+    pub queries_per_second: f64,
+    /// The average number of queries searched together on a shard.
+    pub batch_size: f64,
+    /// The fraction of time the slowest shard is busy.
+    pub utilization: f64,
+    /// The mean query latency including batch formation and queueing, or `None` if the
+    /// shards cannot keep up with the query rate.
+    pub latency: Option<Seconds>,
+    /// The mean latency of the same query rate without batching, for comparison.
+    pub unbatched_latency: Option<Seconds>,
+    /// The highest query rate the shards can sustain with this configuration.
+    pub max_queries_per_second: f64,
+}
+
+impl BatchingPolicy {
+    pub fn unbatched() -> Self {
+        Self {
+            max_batch_size: 1,
+            max_wait: Seconds(0.),
+        }
+    }
+
+    pub fn new<W>(max_batch_size: usize, max_wait: W) -> Self
+    where
+        W: Into<Seconds>,
+    {
+        assert_ne!(max_batch_size, 0);
+        Self {
+            max_batch_size,
+            max_wait: max_wait.into(),
+        }
+    }
+
+    /// The expected batch size and the average time a query waits for its batch to
+    /// close, at the given arrival rate.
+    pub fn formation(&self, queries_per_second: f64) -> (f64, Seconds) {
+        if self.max_batch_size == 1 || queries_per_second <= 0. {
+            return (1., Seconds(0.));
+        }
+
+        let fill_time = (self.max_batch_size - 1) as f64 / queries_per_second;
+        let window = fill_time.min(*self.max_wait);
+        let batch_size = (1. + queries_per_second * window).min(self.max_batch_size as f64);
+        (batch_size, Seconds(window / 2.))
+    }
+
+    /// The policy in units of incoming batches of the given size: as many of them are
+    /// merged as fit into a batch, but at least one.
+    fn merging(&self, incoming_batch: f64) -> Self {
+        Self {
+            max_batch_size: ((self.max_batch_size as f64 / incoming_batch).floor() as usize).max(1),
+            max_wait: self.max_wait,
+        }
+    }
+}
+
+impl BatchCostModel {
+    pub fn new(memory_bound_fraction: f64) -> Self {
+        assert!((0. ..=1.).contains(&memory_bound_fraction));
+        Self {
+            memory_bound_fraction,
+        }
+    }
+
+    /// The time it takes to search a batch, given the time for a single query.
+    pub fn batch_cost(&self, single_query: Seconds, batch_size: f64) -> Seconds {
+        let m = self.memory_bound_fraction;
+        Seconds(*single_query * (m + (1. - m) * batch_size))
+    }
+}
+
+impl BatchingConfig {
+    pub fn unbatched(cost: BatchCostModel) -> Self {
+        Self {
+            coordinator: BatchingPolicy::unbatched(),
+            shard: BatchingPolicy::unbatched(),
+            cost,
+        }
+    }
+}
+
+impl Simulation {
+    /// Estimates latency and throughput of an index when queries arriving at the given
+    /// rate are batched at the coordinator and at the shards.
+    ///
+    /// Batches formed at the coordinator arrive at the shards as a whole; the shards
+    /// may merge as many of them as fit into their own batches, collecting them at the
+    /// rate the coordinator emits batches. Queueing at the slowest shard is approximated
+    /// as an M/D/1 queue of batches.
+    pub fn simulate_batched(
+        &self,
+        index_id: usize,
+        config: &BatchingConfig,
+        queries_per_second: f64,
+    ) -> BatchingResult {
+        let index = self.index(index_id);
+        let shard_ids = self.routing.select_shards(index, None);
+        let slowest_shard = self
+            .shard_completion_times(index, &shard_ids)
+            .into_iter()
+            .fold(Seconds(0.), |a, b| Seconds(a.0.max(b.0)));
+        let (overhead, _) = self.scatter_gather_overhead(shard_ids.len(), index.vector_length);
+
+        let estimate = |config: &BatchingConfig| {
+            let (coordinator_batch, coordinator_wait) =
+                config.coordinator.formation(queries_per_second);
+            let (merged, shard_wait) = config
+                .shard
+                .merging(coordinator_batch)
+                .formation(queries_per_second / coordinator_batch);
+            let batch_size = coordinator_batch * merged;
+
+            let service = config.cost.batch_cost(slowest_shard, batch_size);
+            let utilization = queries_per_second * *service / batch_size;
+            let latency = (utilization < 1.).then(|| {
+                let queueing = utilization * *service / (2. * (1. - utilization));
+                coordinator_wait + shard_wait + overhead + service + Seconds(queueing)
+            });
+            (batch_size, utilization, latency)
+        };
+
+        let (batch_size, utilization, latency) = estimate(config);
+        let (_, _, unbatched_latency) = estimate(&BatchingConfig::unbatched(config.cost));
+
+        let coordinator_max = config.coordinator.max_batch_size as f64;
+        let max_batch =
+            coordinator_max * config.shard.merging(coordinator_max).max_batch_size as f64;
+        let max_queries_per_second = max_batch / *config.cost.batch_cost(slowest_shard, max_batch);
+
+        BatchingResult {
+            queries_per_second,
+            batch_size,
+            utilization,
+            latency,
+            unbatched_latency,
+            max_queries_per_second,
+        }
+    }
+
+    /// Evaluates [`Simulation::simulate_batched`] for each of the given query rates,
+    /// tracing the throughput/latency trade-off of the configuration.
+    pub fn batching_curve(
+        &self,
+        index_id: usize,
+        config: &BatchingConfig,
+        rates: &[f64],
+    ) -> Vec<BatchingResult> {
+        rates
+            .iter()
+            .map(|&qps| self.simulate_batched(index_id, config, qps))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 4], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .with_threads(16, Nanoseconds(100.))
+            .build()
+    }
+
+    #[test]
+    fn unbatched_matches_simulate_find_at_low_load() {
+        let simulation = simulation();
+        let config = BatchingConfig::unbatched(BatchCostModel::new(0.8));
+        let result = simulation.simulate_batched(0, &config, 1e-6);
+        let expected = simulation.simulate_find(0).duration;
+        assert!((*result.latency.unwrap() - *expected).abs() < 1e-6);
+        assert_eq!(result.batch_size, 1.);
+    }
+
+    #[test]
+    fn batching_raises_throughput() {
+        let simulation = simulation();
+        let config = BatchingConfig {
+            coordinator: BatchingPolicy::new(8, Milliseconds(5.)),
+            shard: BatchingPolicy::new(32, Milliseconds(50.)),
+            cost: BatchCostModel::new(0.8),
+        };
+
+        let curve = simulation.batching_curve(0, &config, &[10., 100., 300.]);
+        let unbatched = BatchingConfig::unbatched(config.cost);
+        let unbatched = simulation.simulate_batched(0, &unbatched, 10.);
+
+        assert!(curve[0].latency.unwrap() > curve[0].unbatched_latency.unwrap());
+        assert!(curve[2].latency.is_some());
+        assert!(curve[2].unbatched_latency.is_none());
+        assert!(curve[2].batch_size > curve[0].batch_size);
+        assert!(curve[0].max_queries_per_second > 4. * unbatched.max_queries_per_second);
+    }
+
+    #[test]
+    fn shards_merge_coordinator_batches() {
+        // A single shard searching for 1 ms behind 2 ms of scatter-gather overhead.
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new(0, 1_000_000, 1))
+            .with_search_cost(Nanoseconds(1.), Nanoseconds(0.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .build();
+        let config = BatchingConfig {
+            coordinator: BatchingPolicy::new(4, Milliseconds(100.)),
+            shard: BatchingPolicy::new(16, Milliseconds(200.)),
+            cost: BatchCostModel::new(0.5),
+        };
+        let result = simulation.simulate_batched(0, &config, 100.);
+
+        // The coordinator fills batches of 4 within 30 ms. The shard receives 25 of them
+        // per second and merges 4 within 120 ms, so a batch of 16 takes 8.5 ms to search.
+        assert!((result.batch_size - 16.).abs() < 1e-9);
+        let utilization = 100. * 8.5e-3 / 16.;
+        assert!((result.utilization - utilization).abs() < 1e-9);
+        let queueing = utilization * 8.5e-3 / (2. * (1. - utilization));
+        let expected = 15e-3 + 60e-3 + 2e-3 + 8.5e-3 + queueing;
+        assert!((*result.latency.unwrap() - expected).abs() < 1e-9);
+        assert!((result.max_queries_per_second - 16. / 8.5e-3).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::simulation::{Simulation, SimulationResult};
+use crate::timing::Seconds;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeSet, HashMap};
+
+/// Decides which entry leaves a full cache.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry.
+    #[default]
+    Lru,
+    /// Evict the least frequently used entry, breaking ties by recency.
+    Lfu,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CacheConfig {
+    pub policy: EvictionPolicy,
+    /// The maximum number of cached query results.
+    pub capacity: usize,
+    /// The time after which a cached result is considered stale.
+    pub ttl: Option<Seconds>,
+    /// The time it takes to look up a query in the cache.
+    pub lookup_cost: Seconds,
+}
+
+impl CacheConfig {
+    pub fn new<L>(policy: EvictionPolicy, capacity: usize, lookup_cost: L) -> Self
+    where
+        L: Into<Seconds>,
+    {
+        assert_ne!(capacity, 0);
+        Self {
+            policy,
+            capacity,
+            ttl: None,
+            lookup_cost: lookup_cost.into(),
+        }
+    }
+
+    pub fn with_ttl<T>(mut self, ttl: T) -> Self
+    where
+        T: Into<Seconds>,
+    {
+        self.ttl = Some(ttl.into());
+        self
+    }
+}
+
+struct CacheEntry {
+    inserted_at: f64,
+    last_used: u64,
+    uses: u64,
+}
+
+/// A query result cache keyed by query ID.
+pub struct Cache {
+    config: CacheConfig,
+    entries: HashMap<usize, CacheEntry>,
+    eviction_order: BTreeSet<(u64, u64, usize)>,
+    tick: u64,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            eviction_order: BTreeSet::new(),
+            tick: 0,
+        }
+    }
+
+    /// Looks up a query at time `now`, caching it on a miss. Returns whether it was a hit.
+    pub fn access(&mut self, query: usize, now: Seconds) -> bool {
+        self.tick += 1;
+        if let Some(entry) = self.entries.remove(&query) {
+            self.eviction_order
+                .remove(&self.eviction_key(query, &entry));
+            let expired = self
+                .config
+                .ttl
+                .is_some_and(|ttl| *now - entry.inserted_at > *ttl);
+            if !expired {
+                let entry = CacheEntry {
+                    last_used: self.tick,
+                    uses: entry.uses + 1,
+                    ..entry
+                };
+                self.insert(query, entry);
+                return true;
+            }
+        }
+
+        if self.entries.len() >= self.config.capacity {
+            if let Some(victim) = self.eviction_order.pop_first() {
+                self.entries.remove(&victim.2);
+            }
+        }
+        let entry = CacheEntry {
+            inserted_at: *now,
+            last_used: self.tick,
+            uses: 1,
+        };
+        self.insert(query, entry);
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn insert(&mut self, query: usize, entry: CacheEntry) {
+        self.eviction_order.insert(self.eviction_key(query, &entry));
+        self.entries.insert(query, entry);
+    }
+
+    fn eviction_key(&self, query: usize, entry: &CacheEntry) -> (u64, u64, usize) {
+        match self.config.policy {
+            EvictionPolicy::Lru => (entry.last_used, 0, query),
+            EvictionPolicy::Lfu => (entry.uses, entry.last_used, query),
+        }
+    }
+}
+
+/// Samples query IDs `0..n` where the query of rank `k` is drawn with a probability
+/// proportional to `1 / (k + 1)^exponent`.
+#[derive(Debug, Clone)]
+pub struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    pub fn new(num_items: usize, exponent: f64) -> Self {
+        assert_ne!(num_items, 0);
+        let mut cdf: Vec<f64> = (1..=num_items)
+            .scan(0., |sum, k| {
+                *sum += 1. / (k as f64).powf(exponent);
+                Some(*sum)
+            })
+            .collect();
+        let total = cdf[num_items - 1];
+        cdf.iter_mut().for_each(|p| *p /= total);
+        Self { cdf }
+    }
+
+    pub fn sample<R>(&self, rng: &mut R) -> usize
+    where
+        R: Rng,
+    {
+        let u = rng.gen::<f64>();
+        self.cdf.partition_point(|&p| p < u).min(self.cdf.len() - 1)
+    }
+}
+
+/// A stream of queries with Zipf-distributed popularity arriving at a fixed rate.
+#[derive(Debug, Copy, Clone)]
+pub struct QueryWorkload {
+    pub num_distinct_queries: usize,
+    pub zipf_exponent: f64,
+    pub queries_per_second: f64,
+    pub num_queries: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug)]
+pub struct CacheReport {
+    /// The average query durations with caching enabled. Queries answered by the
+    /// coordinator cache visit no shards, so `shards_visited` is the rounded average
+    /// fan-out over all queries.
+    pub result: SimulationResult,
+    /// The fraction of queries answered by the coordinator cache.
+    pub hit_rate: f64,
+    /// The fraction of shard requests answered by the shard caches.
+    pub shard_hit_rate: f64,
+    /// The relative reduction of the average duration compared to an uncached query.
+    pub latency_improvement: f64,
+}
+
+impl Simulation {
+    /// Replays a query workload against an index fronted by an optional coordinator
+    /// cache and optional per-shard caches.
+    pub fn simulate_cached(
+        &self,
+        index_id: usize,
+        workload: &QueryWorkload,
+        coordinator: Option<CacheConfig>,
+        shard: Option<CacheConfig>,
+    ) -> CacheReport {
+        let mut rng = StdRng::seed_from_u64(workload.seed);
+        let zipf = Zipf::new(workload.num_distinct_queries, workload.zipf_exponent);
+        let index = self.index(index_id);
+        let uncached = self.simulate_find(index_id);
+
+        let shard_ids = self.routing.select_shards(index, None);
+        let search_times = self.shard_completion_times(index, &shard_ids);
+        let (overhead, overhead_total) =
+            self.scatter_gather_overhead(shard_ids.len(), index.vector_length);
+
+        let mut coordinator_cache = coordinator.map(Cache::new);
+        let mut shard_caches: Vec<Cache> = shard
+            .map(|config| shard_ids.iter().map(|_| Cache::new(config)).collect())
+            .unwrap_or_default();
+
+        let (mut duration, mut duration_total) = (Seconds(0.), Seconds(0.));
+        let (mut hits, mut shard_hits) = (0, 0);
+        for i in 0..workload.num_queries {
+            let now = Seconds(i as f64 / workload.queries_per_second);
+            let query = zipf.sample(&mut rng);
+
+            if let Some(cache) = &mut coordinator_cache {
+                duration += cache.config.lookup_cost;
+                duration_total += cache.config.lookup_cost;
+                if cache.access(query, now) {
+                    hits += 1;
+                    continue;
+                }
+            }
+
+            let mut slowest = Seconds(0.);
+            for (position, search_time) in search_times.iter().enumerate() {
+                let shard_time = match shard_caches.get_mut(position) {
+                    Some(cache) => {
+                        if cache.access(query, now) {
+                            shard_hits += 1;
+                            cache.config.lookup_cost
+                        } else {
+                            cache.config.lookup_cost + *search_time
+                        }
+                    }
+                    None => *search_time,
+                };
+                slowest = Seconds(slowest.0.max(shard_time.0));
+                duration_total += shard_time;
+            }
+            duration += slowest + overhead;
+            duration_total += overhead_total;
+        }
+
+        let queries = workload.num_queries.max(1) as f64;
+        let duration = Seconds(*duration / queries);
+        let shard_requests = (workload.num_queries - hits) * shard_ids.len();
+        CacheReport {
+            latency_improvement: 1. - *duration / *uncached.duration,
+            result: SimulationResult {
+                duration,
+                duration_total: Seconds(*duration_total / queries),
+                shards_visited: (shard_requests as f64 / queries).round() as usize,
+                // Cached results are those of the uncached queries that populated the cache.
+                recall: uncached.recall,
+            },
+            hit_rate: hits as f64 / queries,
+            shard_hit_rate: if shard_caches.is_empty() {
+                0.
+            } else {
+                shard_hits as f64 / shard_requests.max(1) as f64
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::routing::RecallModel;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Microseconds, Milliseconds, Nanoseconds};
+
+    #[test]
+    fn lru_and_lfu_evict_differently() {
+        let config = CacheConfig::new(EvictionPolicy::Lru, 2, Seconds(0.));
+        let mut lru = Cache::new(config);
+        let mut lfu = Cache::new(CacheConfig {
+            policy: EvictionPolicy::Lfu,
+            ..config
+        });
+
+        for cache in [&mut lru, &mut lfu] {
+            cache.access(1, Seconds(0.));
+            cache.access(1, Seconds(0.));
+            cache.access(2, Seconds(0.));
+            cache.access(3, Seconds(0.));
+            assert_eq!(cache.len(), 2);
+        }
+
+        assert!(!lru.access(1, Seconds(0.)));
+        assert!(lfu.access(1, Seconds(0.)));
+    }
+
+    #[test]
+    fn ttl_expires_entries() {
+        let config = CacheConfig::new(EvictionPolicy::Lru, 10, Seconds(0.)).with_ttl(Seconds(1.));
+        let mut cache = Cache::new(config);
+        assert!(!cache.access(1, Seconds(0.)));
+        assert!(cache.access(1, Seconds(0.5)));
+        assert!(!cache.access(1, Seconds(2.)));
+    }
+
+    #[test]
+    fn caching_reduces_latency() {
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 4], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .build();
+        let workload = QueryWorkload {
+            num_distinct_queries: 10_000,
+            zipf_exponent: 1.1,
+            queries_per_second: 100.,
+            num_queries: 5_000,
+            seed: 3,
+        };
+
+        let cache = CacheConfig::new(EvictionPolicy::Lfu, 500, Microseconds(50.));
+        let report = simulation.simulate_cached(0, &workload, Some(cache), None);
+        assert!(report.hit_rate > 0.3 && report.hit_rate < 1.);
+        assert!(report.latency_improvement > 0.3);
+        assert_eq!(report.shard_hit_rate, 0.);
+        assert!(report.result.shards_visited < 4);
+
+        let report = simulation.simulate_cached(0, &workload, None, Some(cache));
+        assert_eq!(report.hit_rate, 0.);
+        assert!(report.shard_hit_rate > 0.3);
+    }
+
+    #[test]
+    fn uncached_replay_matches_routed_find() {
+        let index = Index::new_from_shards(0, &[1_000_000; 8], 2);
+        for (i, shard_id) in index.shard_ids().into_iter().enumerate() {
+            index.set_centroid(shard_id, vec![i as f32, 0.]).unwrap();
+        }
+        let simulation = SimulationBuilder::default()
+            .with_index(index)
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .with_shard_pruning(2, RecallModel::Uniform)
+            .build();
+        let workload = QueryWorkload {
+            num_distinct_queries: 100,
+            zipf_exponent: 1.,
+            queries_per_second: 100.,
+            num_queries: 100,
+            seed: 3,
+        };
+
+        let report = simulation.simulate_cached(0, &workload, None, None);
+        assert_eq!(report.result.shards_visited, 2);
+        assert!(report.latency_improvement.abs() < 1e-9);
+    }
+}
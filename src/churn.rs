@@ -0,0 +1,304 @@
+use crate::index::{AssignmentError, ShardId};
+use crate::load::{rebalance, BalanceObjective, Move};
+use crate::simulation::Simulation;
+use crate::timing::Seconds;
+use std::collections::HashMap;
+
+/// The rate at which vectors are inserted into and deleted from a shard.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ShardChurn {
+    pub inserts_per_second: f64,
+    pub deletes_per_second: f64,
+}
+
+/// Conditions under which the index is rebalanced automatically.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RebalanceTriggers {
+    /// Rebalance by size once the largest shard exceeds the mean by this ratio.
+    pub max_imbalance: Option<f64>,
+    /// Split a shard in half once it holds more than this many vectors.
+    pub max_shard_size: Option<usize>,
+}
+
+/// A time-evolving scenario in which the shards of an index grow and shrink.
+#[derive(Debug, Clone)]
+pub struct ChurnScenario {
+    pub duration: Seconds,
+    /// The interval at which churn is applied and triggers are evaluated.
+    pub step: Seconds,
+    /// The churn of shards without an explicit rate, including shards created by splits.
+    pub default_churn: ShardChurn,
+    pub shard_churn: HashMap<ShardId, ShardChurn>,
+    pub triggers: RebalanceTriggers,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RebalanceAction {
+    /// A shard was split by moving half of its vectors into a new shard.
+    Split { source: ShardId, target: ShardId },
+    /// Vectors were moved between existing shards to equalize their size.
+    Rebalance { moves: Vec<Move> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceEvent {
+    pub time: Seconds,
+    pub action: RebalanceAction,
+}
+
+/// The state of the index at the end of a step.
+#[derive(Debug, Clone)]
+pub struct TimelineSample {
+    pub time: Seconds,
+    /// The number of vectors of each shard, ordered by shard ID.
+    pub shard_sizes: Vec<(ShardId, usize)>,
+    pub latency: Seconds,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChurnTimeline {
+    pub samples: Vec<TimelineSample>,
+    pub events: Vec<RebalanceEvent>,
+}
+
+impl ChurnScenario {
+    pub fn new<D, S>(duration: D, step: S, default_churn: ShardChurn) -> Self
+    where
+        D: Into<Seconds>,
+        S: Into<Seconds>,
+    {
+        let step = step.into();
+        assert!(*step > 0.);
+        Self {
+            duration: duration.into(),
+            step,
+            default_churn,
+            shard_churn: HashMap::new(),
+            triggers: RebalanceTriggers::default(),
+        }
+    }
+
+    pub fn with_shard_churn(mut self, shard_id: ShardId, churn: ShardChurn) -> Self {
+        self.shard_churn.insert(shard_id, churn);
+        self
+    }
+
+    pub fn with_triggers(mut self, triggers: RebalanceTriggers) -> Self {
+        self.triggers = triggers;
+        self
+    }
+
+    fn churn(&self, shard_id: ShardId) -> ShardChurn {
+        self.shard_churn
+            .get(&shard_id)
+            .cloned()
+            .unwrap_or(self.default_churn)
+    }
+}
+
+impl Simulation {
+    /// Runs a churn scenario against an index, applying inserts and deletes step by step
+    /// and firing the rebalancing triggers. The index is left in its final state.
+    pub fn simulate_churn(
+        &mut self,
+        index_id: usize,
+        scenario: &ChurnScenario,
+    ) -> Result<ChurnTimeline, AssignmentError> {
+        let mut timeline = ChurnTimeline {
+            samples: Vec::new(),
+            events: Vec::new(),
+        };
+        // Fractional vectors carried over to the next step.
+        let mut pending: HashMap<ShardId, (f64, f64)> = HashMap::new();
+        let steps = (*scenario.duration / *scenario.step).ceil() as usize;
+
+        for step in 1..=steps {
+            let time = Seconds(*scenario.step * step as f64);
+            let index = self.index_mut(index_id);
+
+            for shard_id in index.shard_ids() {
+                let churn = scenario.churn(shard_id);
+                let (inserts, deletes) = pending.entry(shard_id).or_default();
+                *inserts += churn.inserts_per_second * *scenario.step;
+                *deletes += churn.deletes_per_second * *scenario.step;
+
+                let inserted = inserts.floor() as usize;
+                *inserts -= inserted as f64;
+                index.insert(shard_id, inserted).unwrap();
+
+                let size = index.shard(shard_id).unwrap().num_vectors;
+                // Deletes exceeding the shard's size stay pending until it grows again.
+                let deleted = (deletes.floor() as usize).min(size);
+                *deletes -= deleted as f64;
+                index.delete(shard_id, deleted)?;
+            }
+
+            if let Some(max_shard_size) = scenario.triggers.max_shard_size {
+                // Halves of oversized shards are split again until all of them fit.
+                let mut oversized = index.shard_ids();
+                while let Some(source) = oversized.pop() {
+                    let size = index.shard(source).unwrap().num_vectors;
+                    if size > max_shard_size {
+                        let target = index.create_empty_shard();
+                        index.move_data(source, target, size / 2)?;
+                        timeline.events.push(RebalanceEvent {
+                            time,
+                            action: RebalanceAction::Split { source, target },
+                        });
+                        oversized.extend([source, target]);
+                    }
+                }
+            }
+
+            if let Some(max_imbalance) = scenario.triggers.max_imbalance {
+                let moves = rebalance(index, BalanceObjective::Size, max_imbalance)?;
+                if !moves.is_empty() {
+                    timeline.events.push(RebalanceEvent {
+                        time,
+                        action: RebalanceAction::Rebalance { moves },
+                    });
+                }
+            }
+
+            let index = self.index(index_id);
+            timeline.samples.push(TimelineSample {
+                time,
+                shard_sizes: index
+                    .shard_ids()
+                    .into_iter()
+                    .map(|id| (id, index.shard(id).unwrap().num_vectors))
+                    .collect(),
+                latency: self.simulate_find(index_id).duration,
+            });
+        }
+
+        Ok(timeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::segments::{CompactionPolicy, SegmentConfig};
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Microseconds, Nanoseconds};
+
+    fn simulation(shards: &[usize]) -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, shards, 128))
+            .with_search_cost(Nanoseconds(1.), Nanoseconds(0.))
+            .build()
+    }
+
+    fn churn(inserts_per_second: f64, deletes_per_second: f64) -> ShardChurn {
+        ShardChurn {
+            inserts_per_second,
+            deletes_per_second,
+        }
+    }
+
+    #[test]
+    fn uneven_growth_raises_latency() {
+        let mut simulation = simulation(&[1000, 1000]);
+        let hot = simulation.index(0).shard_ids()[0];
+        let scenario = ChurnScenario::new(Seconds(10.), Seconds(1.), churn(10., 10.))
+            .with_shard_churn(hot, churn(100.5, 0.));
+
+        let timeline = simulation.simulate_churn(0, &scenario).unwrap();
+        assert_eq!(timeline.samples.len(), 10);
+        assert!(timeline.events.is_empty());
+
+        let last = timeline.samples.last().unwrap();
+        assert_eq!(last.shard_sizes[0].1, 2005);
+        assert_eq!(last.shard_sizes[1].1, 1000);
+        assert!(last.latency > timeline.samples[0].latency);
+        assert_eq!(simulation.index(0).len(), 3005);
+    }
+
+    #[test]
+    fn large_steps_split_repeatedly() {
+        let mut simulation = simulation(&[1000]);
+        let scenario = ChurnScenario::new(Seconds(1.), Seconds(1.), churn(7000., 0.))
+            .with_triggers(RebalanceTriggers {
+                max_imbalance: None,
+                max_shard_size: Some(1000),
+            });
+        let timeline = simulation.simulate_churn(0, &scenario).unwrap();
+        assert_eq!(timeline.events.len(), 7);
+        let index = simulation.index(0);
+        assert_eq!((index.num_shards(), index.len()), (8, 8000));
+        for shard in index {
+            assert!(shard.num_vectors <= 1000);
+        }
+    }
+
+    #[test]
+    fn triggers_split_and_rebalance() {
+        let mut simulation = simulation(&[1000, 1000]);
+        let hot = simulation.index(0).shard_ids()[0];
+        let scenario = ChurnScenario::new(Seconds(10.), Seconds(1.), ShardChurn::default())
+            .with_shard_churn(hot, churn(200., 0.))
+            .with_triggers(RebalanceTriggers {
+                max_imbalance: Some(1.2),
+                max_shard_size: Some(1500),
+            });
+
+        let timeline = simulation.simulate_churn(0, &scenario).unwrap();
+        assert!(timeline
+            .events
+            .iter()
+            .any(|e| matches!(e.action, RebalanceAction::Split { .. })));
+        assert!(timeline
+            .events
+            .iter()
+            .any(|e| matches!(e.action, RebalanceAction::Rebalance { .. })));
+
+        let index = simulation.index(0);
+        assert_eq!(index.len(), 4000);
+        assert!(index.num_shards() > 2);
+        for shard in index {
+            assert!(shard.num_vectors <= 1500);
+        }
+    }
+
+    #[test]
+    fn churn_keeps_segment_overhead() {
+        let build = |shards: &[usize]| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, shards, 128))
+                .with_search_cost(Nanoseconds(1.), Nanoseconds(0.))
+                .with_segment_search_cost(Microseconds(100.))
+                .build()
+        };
+        let mut simulation = build(&[10_000, 10_000]);
+        let config = SegmentConfig::new(
+            100,
+            CompactionPolicy::Tiered {
+                segments_per_tier: 10,
+            },
+        );
+        simulation.ingest_segmented(0, 1_000, &config);
+
+        let scenario = ChurnScenario::new(Seconds(10.), Seconds(1.), churn(20., 10.));
+        let timeline = simulation.simulate_churn(0, &scenario).unwrap();
+        let index = simulation.index(0);
+        for shard in index {
+            let layout = shard.segments.as_ref().unwrap();
+            assert_eq!(layout.num_vectors(), shard.num_vectors);
+            assert!(layout.searched_segments() > 1);
+        }
+
+        // The same shard sizes without segments search faster.
+        let sizes: Vec<usize> = timeline
+            .samples
+            .last()
+            .unwrap()
+            .shard_sizes
+            .iter()
+            .map(|s| s.1)
+            .collect();
+        let plain = build(&sizes).simulate_find(0).duration;
+        assert!(timeline.samples.last().unwrap().latency > plain);
+    }
+}
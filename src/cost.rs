@@ -0,0 +1,236 @@
+use crate::hardware::{HardwareProfile, VECTOR_ELEMENT_BYTES};
+use crate::index::Index;
+use crate::simulation::Simulation;
+use crate::timing::Seconds;
+
+/// The average number of hours in a month.
+pub const HOURS_PER_MONTH: f64 = 730.;
+
+/// The query rate a cluster has to sustain and the latency it has to meet.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CostTarget {
+    pub queries_per_second: f64,
+    pub latency_slo: Seconds,
+}
+
+#[derive(Debug)]
+pub struct CostReport {
+    /// The price of running all nodes for a month.
+    pub monthly_cost: f64,
+    pub cost_per_million_queries: f64,
+    /// The fraction of the busiest node's cores spent searching at the target rate.
+    pub utilization: f64,
+    /// The mean query latency at the target rate, or `None` if a node is overloaded.
+    pub latency: Option<Seconds>,
+    pub meets_slo: bool,
+}
+
+/// A cluster layout evaluated by [`Simulation::cheapest_layout`].
+#[derive(Debug)]
+pub struct Layout {
+    /// The name of the hardware profile all nodes use.
+    pub profile: String,
+    pub num_nodes: usize,
+    /// The number of shards, one per node.
+    pub num_shards: usize,
+    pub report: CostReport,
+}
+
+impl Simulation {
+    /// The price of running all nodes of the simulation for a month.
+    pub fn monthly_cost(&self) -> f64 {
+        self.node_profiles()
+            .iter()
+            .map(|profile| profile.hourly_price * HOURS_PER_MONTH)
+            .sum()
+    }
+
+    /// Reports the cost of serving an index at the target rate, and whether the
+    /// latency SLO is met. Queries only load the shards selected by the routing, and
+    /// searches on a node are slowed down by the share of its cores busy with other
+    /// queries.
+    pub fn cost_report(&self, index_id: usize, target: &CostTarget) -> CostReport {
+        let index = self.index(index_id);
+        let mut cpu = vec![0.; self.num_nodes];
+        for shard_id in self.routing.select_shards(index, None) {
+            let shard = index.shard(shard_id).unwrap();
+            let (_, search_cpu) = self.shard_search_time(&shard);
+            cpu[self.node_of(&shard)] += target.queries_per_second * *search_cpu;
+        }
+        let utilization = (0..self.num_nodes)
+            .map(|node| {
+                let cores = match self.node_profiles().get(node) {
+                    Some(profile) => profile.cores,
+                    None => self.thread_count,
+                };
+                cpu[node] / cores as f64
+            })
+            .fold(0., f64::max);
+
+        let duration = self.simulate_find(index_id).duration;
+        let latency = (utilization < 1.).then(|| Seconds(*duration / (1. - utilization)));
+        let monthly_cost = self.monthly_cost();
+        let queries_per_month = target.queries_per_second * HOURS_PER_MONTH * 3600.;
+
+        CostReport {
+            monthly_cost,
+            cost_per_million_queries: monthly_cost / queries_per_month * 1e6,
+            utilization,
+            meets_slo: latency.is_some_and(|latency| latency <= target.latency_slo),
+            latency,
+        }
+    }
+
+    /// Evaluates clusters of `1..=max_nodes` identical nodes of every given profile,
+    /// each holding one evenly sized shard of the index, and returns the cheapest one
+    /// that fits the index into memory and meets the target. Ties are broken by latency.
+    pub fn cheapest_layout(
+        &self,
+        index_id: usize,
+        profiles: &[HardwareProfile],
+        max_nodes: usize,
+        target: &CostTarget,
+    ) -> Option<Layout> {
+        let index = self.index(index_id);
+        // Candidates only hold the target index, re-sharded for every layout.
+        let mut candidate = self.without_indexes();
+        let mut best: Option<Layout> = None;
+        for profile in profiles {
+            for num_nodes in 1..=max_nodes {
+                let shard_bytes = index.num_vectors.div_ceil(num_nodes)
+                    * index.vector_length
+                    * VECTOR_ELEMENT_BYTES;
+                if shard_bytes > profile.ram_bytes {
+                    continue;
+                }
+
+                candidate.node_profiles = vec![profile.clone(); num_nodes];
+                candidate.num_nodes = num_nodes;
+                candidate.insert_index(
+                    Index::new_evenly_split(
+                        index_id,
+                        index.num_vectors,
+                        num_nodes,
+                        index.vector_length,
+                    )
+                    .with_filter_strategy(index.filter_strategy),
+                );
+
+                let report = candidate.cost_report(index_id, target);
+                if !report.meets_slo {
+                    continue;
+                }
+                let better = best.as_ref().is_none_or(|b| {
+                    (report.monthly_cost, report.latency)
+                        < (b.report.monthly_cost, b.report.latency)
+                });
+                if better {
+                    best = Some(Layout {
+                        profile: profile.name.clone(),
+                        num_nodes,
+                        num_shards: num_nodes,
+                        report,
+                    });
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::RecallModel;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 2], 128))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .with_threads(8, Nanoseconds(0.))
+            .with_node_profiles(vec![
+                HardwareProfile::new("small", Nanoseconds(0.2), 8)
+                    .with_hourly_price(0.5);
+                2
+            ])
+            .build()
+    }
+
+    #[test]
+    fn cost_per_query_follows_price_and_rate() {
+        let simulation = simulation();
+        let target = CostTarget {
+            queries_per_second: 100.,
+            latency_slo: Milliseconds(100.).into(),
+        };
+        let report = simulation.cost_report(0, &target);
+        assert_eq!(report.monthly_cost, 730.);
+        assert!((report.cost_per_million_queries - 730. / 262.8).abs() < 1e-9);
+        // Each node spends 25.6ms of CPU per query on its shard.
+        assert!((report.utilization - 0.32).abs() < 1e-9);
+        assert!(report.meets_slo);
+
+        let target = CostTarget {
+            queries_per_second: 400.,
+            ..target
+        };
+        assert!(simulation.cost_report(0, &target).latency.is_none());
+    }
+
+    #[test]
+    fn pruned_shards_cost_no_cpu() {
+        let build = |pruned: bool| {
+            let index = Index::new_from_shards(0, &[1_000_000; 4], 128);
+            for (i, shard_id) in index.shard_ids().into_iter().enumerate() {
+                index.set_centroid(shard_id, vec![i as f32; 128]).unwrap();
+            }
+            let builder = SimulationBuilder::default()
+                .with_index(index)
+                .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+                .with_threads(8, Nanoseconds(0.))
+                .with_nodes(2);
+            if pruned {
+                builder.with_shard_pruning(2, RecallModel::Uniform).build()
+            } else {
+                builder.build()
+            }
+        };
+        let target = CostTarget {
+            queries_per_second: 100.,
+            latency_slo: Milliseconds(100.).into(),
+        };
+
+        // Every node hosts two of the shards, but the pruned query visits one per node.
+        let full = build(false).cost_report(0, &target);
+        let pruned = build(true).cost_report(0, &target);
+        assert!((full.utilization - 0.64).abs() < 1e-9);
+        assert!((pruned.utilization - 0.32).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cheapest_layout_meets_slo() {
+        let simulation = simulation();
+        let small = HardwareProfile::new("small", Nanoseconds(0.2), 8).with_hourly_price(0.5);
+        let large = HardwareProfile::new("large", Nanoseconds(0.2), 32).with_hourly_price(2.2);
+        let target = CostTarget {
+            queries_per_second: 400.,
+            latency_slo: Milliseconds(20.).into(),
+        };
+
+        let layout = simulation
+            .cheapest_layout(0, &[small.clone(), large], 16, &target)
+            .unwrap();
+        // Small nodes are cheaper per core, but the scatter-gather overhead of the many
+        // nodes needed for the load breaks the SLO.
+        assert!(layout.report.meets_slo);
+        assert_eq!(layout.profile, "large");
+        assert_eq!((layout.num_nodes, layout.num_shards), (2, 2));
+        assert!((layout.report.monthly_cost - 2. * 2.2 * HOURS_PER_MONTH).abs() < 1e-9);
+        assert!(simulation
+            .cheapest_layout(0, &[small], 16, &target)
+            .is_none());
+    }
+}
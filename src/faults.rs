@@ -0,0 +1,506 @@
+use crate::index::ShardId;
+use crate::placement::{Placement, Replica};
+use crate::simulation::Simulation;
+use crate::stats::LatencyStats;
+use crate::timing::Seconds;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+/// Describes the faults injected into every shard request.
+#[derive(Debug, Clone)]
+pub struct FaultModel {
+    /// The mean time between failures of the node serving a shard.
+    pub node_mtbf: Seconds,
+    /// The mean time it takes to bring a failed node back.
+    pub node_mttr: Seconds,
+    /// The probability that a shard request is served by a straggler.
+    pub straggler_probability: f64,
+    /// The factor by which a straggler's search time is multiplied.
+    pub straggler_slowdown: f64,
+    /// Persistent slowdown factors of individual shards, e.g. due to degraded hardware.
+    pub shard_slowdown: HashMap<ShardId, f64>,
+    /// The probability that a request or its response is lost.
+    pub drop_probability: f64,
+    /// The time after which the coordinator gives up waiting for a shard.
+    pub timeout: Seconds,
+    /// The time between two consecutive queries of a simulation, over which node
+    /// failures and repairs unfold.
+    pub query_interval: Seconds,
+    /// The node serving every replica. Without a placement, replica `r` of a shard is
+    /// served by the `r`-th node after the shard's primary node.
+    pub placement: Option<HashMap<Replica, usize>>,
+}
+
+/// What the coordinator does when a shard does not answer in time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CoordinatorPolicy {
+    /// The whole query fails.
+    FailQuery,
+    /// The query returns the results of the shards that answered.
+    PartialResults,
+    /// The request is re-sent to a replica of the shard, up to `max_retries` times.
+    /// The query fails if no replica answers.
+    RetryOnReplica { max_retries: usize },
+}
+
+/// The outcome of a single shard request.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShardOutcome {
+    /// The shard answered after the given time.
+    Responded(Seconds),
+    /// The shard's node was down or the request was dropped.
+    Lost,
+}
+
+#[derive(Debug)]
+pub struct FaultReport {
+    pub num_queries: usize,
+    /// The fraction of queries that returned results.
+    pub availability: f64,
+    /// The fraction of queries that returned results from only some of their shards.
+    pub partial_result_rate: f64,
+    /// The latency of all queries that returned results.
+    pub latency: LatencyStats,
+    /// The duration of the query without any faults, for comparison.
+    pub fault_free_duration: Seconds,
+    /// The number of requests re-sent to replicas.
+    pub retries: usize,
+}
+
+impl FaultModel {
+    /// A model in which every shard answers, subject only to the given timeout.
+    pub fn new<T>(timeout: T) -> Self
+    where
+        T: Into<Seconds>,
+    {
+        Self {
+            node_mtbf: Seconds(f64::INFINITY),
+            node_mttr: Seconds(0.),
+            straggler_probability: 0.,
+            straggler_slowdown: 1.,
+            shard_slowdown: HashMap::new(),
+            drop_probability: 0.,
+            timeout: timeout.into(),
+            query_interval: Seconds(1.),
+            placement: None,
+        }
+    }
+
+    pub fn with_node_failures<F, R>(mut self, mtbf: F, mttr: R) -> Self
+    where
+        F: Into<Seconds>,
+        R: Into<Seconds>,
+    {
+        self.node_mtbf = mtbf.into();
+        self.node_mttr = mttr.into();
+        self
+    }
+
+    pub fn with_query_interval<I>(mut self, interval: I) -> Self
+    where
+        I: Into<Seconds>,
+    {
+        self.query_interval = interval.into();
+        assert!(*self.query_interval >= 0.);
+        self
+    }
+
+    /// Routes requests to the nodes the replicas were placed on.
+    pub fn with_placement(mut self, placement: &Placement) -> Self {
+        self.placement = Some(placement.assignments.clone());
+        self
+    }
+
+    pub fn with_stragglers(mut self, probability: f64, slowdown: f64) -> Self {
+        assert!((0. ..=1.).contains(&probability));
+        assert!(slowdown >= 1.);
+        self.straggler_probability = probability;
+        self.straggler_slowdown = slowdown;
+        self
+    }
+
+    pub fn with_shard_slowdown(mut self, shard_id: ShardId, slowdown: f64) -> Self {
+        assert!(slowdown > 0.);
+        self.shard_slowdown.insert(shard_id, slowdown);
+        self
+    }
+
+    pub fn with_dropped_rpcs(mut self, probability: f64) -> Self {
+        assert!((0. ..=1.).contains(&probability));
+        self.drop_probability = probability;
+        self
+    }
+
+    /// The steady-state probability that a node is down.
+    pub fn unavailability(&self) -> f64 {
+        let mttr = *self.node_mttr;
+        if mttr <= 0. {
+            return 0.;
+        }
+        mttr / (*self.node_mtbf + mttr)
+    }
+
+    /// Samples the outcome of a request to a shard on a node that is up.
+    pub(crate) fn sample_request<R>(
+        &self,
+        rng: &mut R,
+        shard_id: ShardId,
+        base: Seconds,
+    ) -> ShardOutcome
+    where
+        R: Rng,
+    {
+        if rng.gen::<f64>() < self.drop_probability {
+            return ShardOutcome::Lost;
+        }
+
+        let mut slowdown = *self.shard_slowdown.get(&shard_id).unwrap_or(&1.);
+        if rng.gen::<f64>() < self.straggler_probability {
+            slowdown *= self.straggler_slowdown;
+        }
+        ShardOutcome::Responded(Seconds(*base * slowdown))
+    }
+}
+
+/// The alternating up and down periods of a node, with exponentially distributed
+/// times to failure and repair.
+struct NodeTimeline {
+    failure_at: f64,
+    repair_at: f64,
+}
+
+impl NodeTimeline {
+    fn new<R: Rng>(rng: &mut R, faults: &FaultModel) -> Self {
+        let failure_at = exponential(rng, *faults.node_mtbf);
+        Self {
+            failure_at,
+            repair_at: failure_at + exponential(rng, *faults.node_mttr),
+        }
+    }
+
+    fn is_down<R: Rng>(&mut self, rng: &mut R, faults: &FaultModel, time: f64) -> bool {
+        while self.repair_at <= time {
+            self.failure_at = self.repair_at + exponential(rng, *faults.node_mtbf);
+            self.repair_at = self.failure_at + exponential(rng, *faults.node_mttr);
+        }
+        self.failure_at <= time
+    }
+}
+
+/// The up and down periods of every node serving requests.
+pub(crate) struct NodeOutages {
+    nodes: Vec<NodeTimeline>,
+}
+
+impl NodeOutages {
+    /// Starts the timelines of the simulation's nodes and of every node the fault
+    /// model's placement refers to.
+    pub(crate) fn new<R: Rng>(rng: &mut R, faults: &FaultModel, num_nodes: usize) -> Self {
+        let num_nodes = num_nodes.max(
+            faults
+                .placement
+                .iter()
+                .flat_map(|placement| placement.values())
+                .map(|&node| node + 1)
+                .max()
+                .unwrap_or(0),
+        );
+        Self {
+            nodes: (0..num_nodes)
+                .map(|_| NodeTimeline::new(rng, faults))
+                .collect(),
+        }
+    }
+
+    /// Whether each node is down at the given time. Times must not decrease between calls.
+    pub(crate) fn down_at<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        faults: &FaultModel,
+        time: f64,
+    ) -> Vec<bool> {
+        self.nodes
+            .iter_mut()
+            .map(|node| node.is_down(rng, faults, time))
+            .collect()
+    }
+}
+
+fn exponential<R: Rng>(rng: &mut R, mean: f64) -> f64 {
+    -(1. - rng.gen::<f64>()).ln() * mean
+}
+
+impl Simulation {
+    /// The node serving the given replica of a shard whose primary copy is on `primary`.
+    pub(crate) fn node_of_replica(
+        &self,
+        faults: &FaultModel,
+        replica: Replica,
+        primary: usize,
+    ) -> usize {
+        match faults.placement.as_ref().and_then(|p| p.get(&replica)) {
+            Some(&node) => node,
+            None => (primary + replica.replica) % self.num_nodes,
+        }
+    }
+
+    /// Runs `num_queries` queries against an index while injecting faults, and reports
+    /// how the coordinator policy affects availability and latency.
+    ///
+    /// Queries are issued every `faults.query_interval` and fan out to the shards
+    /// selected by the simulation's routing. Nodes fail and recover over time, so an
+    /// outage takes down every shard and replica served by the node until it is
+    /// repaired. Dropped requests and stragglers are drawn per request.
+    pub fn simulate_find_with_faults(
+        &self,
+        index_id: usize,
+        faults: &FaultModel,
+        policy: CoordinatorPolicy,
+        num_queries: usize,
+        seed: u64,
+    ) -> FaultReport {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let index = self.index(index_id);
+        let shard_ids = self.routing.select_shards(index, None);
+        // Shards respond when the pool scheduling of their node completes them.
+        let completion_times = self.shard_completion_times(index, &shard_ids);
+        let shards: Vec<(ShardId, usize, Seconds)> = shard_ids
+            .iter()
+            .zip(&completion_times)
+            .map(|(&shard_id, &time)| {
+                let shard = index.shard(shard_id).unwrap();
+                (shard_id, self.node_of(&shard), time)
+            })
+            .collect();
+        let (overhead, _) = self.scatter_gather_overhead(shards.len(), index.vector_length);
+        let fault_free_duration = completion_times
+            .iter()
+            .fold(Seconds(0.), |a, &b| Seconds(a.0.max(b.0)))
+            + overhead;
+
+        let max_attempts = match policy {
+            CoordinatorPolicy::RetryOnReplica { max_retries } => max_retries + 1,
+            _ => 1,
+        };
+
+        let mut nodes = NodeOutages::new(&mut rng, faults, self.num_nodes);
+
+        let mut latencies = Vec::with_capacity(num_queries);
+        let mut partial = 0;
+        let mut retries = 0;
+        for query in 0..num_queries {
+            let time = *faults.query_interval * query as f64;
+            let down = nodes.down_at(&mut rng, faults, time);
+
+            let mut slowest = Seconds(0.);
+            let mut missing = 0;
+            for &(shard_id, primary, search_time) in &shards {
+                let mut elapsed = Seconds(0.);
+                let mut answered = false;
+                for attempt in 0..max_attempts {
+                    if attempt > 0 {
+                        retries += 1;
+                    }
+                    let replica = Replica {
+                        index_id,
+                        shard_id,
+                        replica: attempt,
+                    };
+                    let outcome = if down[self.node_of_replica(faults, replica, primary)] {
+                        ShardOutcome::Lost
+                    } else {
+                        faults.sample_request(&mut rng, shard_id, search_time)
+                    };
+                    match outcome {
+                        ShardOutcome::Responded(time) if time <= faults.timeout => {
+                            elapsed += time;
+                            answered = true;
+                            break;
+                        }
+                        _ => elapsed += faults.timeout,
+                    }
+                }
+
+                slowest = Seconds(slowest.0.max(elapsed.0));
+                if !answered {
+                    missing += 1;
+                }
+            }
+
+            if missing == 0 {
+                latencies.push(slowest + overhead);
+            } else if policy == CoordinatorPolicy::PartialResults && missing < shards.len() {
+                latencies.push(slowest + overhead);
+                partial += 1;
+            }
+        }
+
+        let queries = num_queries.max(1) as f64;
+        FaultReport {
+            num_queries,
+            availability: latencies.len() as f64 / queries,
+            partial_result_rate: partial as f64 / queries,
+            latency: LatencyStats::from_samples(&latencies),
+            fault_free_duration,
+            retries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::scheduler::PoolScheduling;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 10], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .build()
+    }
+
+    #[test]
+    fn no_faults_match_simulate_find() {
+        let faults = FaultModel::new(Seconds(10.));
+        for scheduling in [PoolScheduling::Dedicated, PoolScheduling::Fifo] {
+            let simulation = SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, &[1_000_000; 10], 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+                .with_threads(4, Nanoseconds(0.))
+                .with_nodes(3)
+                .with_pool_scheduling(scheduling)
+                .build();
+            let report = simulation.simulate_find_with_faults(
+                0,
+                &faults,
+                CoordinatorPolicy::FailQuery,
+                100,
+                1,
+            );
+            assert_eq!(report.availability, 1.);
+            assert_eq!(
+                report.fault_free_duration,
+                simulation.simulate_find(0).duration
+            );
+            assert!((*report.latency.max - *report.fault_free_duration).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn policies_trade_availability_for_latency() {
+        let simulation = simulation();
+        let faults = FaultModel::new(Milliseconds(500.))
+            .with_dropped_rpcs(0.05)
+            .with_stragglers(0.01, 10.);
+
+        let fail =
+            simulation.simulate_find_with_faults(0, &faults, CoordinatorPolicy::FailQuery, 1000, 1);
+        let partial = simulation.simulate_find_with_faults(
+            0,
+            &faults,
+            CoordinatorPolicy::PartialResults,
+            1000,
+            1,
+        );
+        let retry = simulation.simulate_find_with_faults(
+            0,
+            &faults,
+            CoordinatorPolicy::RetryOnReplica { max_retries: 2 },
+            1000,
+            1,
+        );
+
+        assert!(fail.availability < 0.7);
+        assert_eq!(partial.availability, 1.);
+        assert!(partial.partial_result_rate > 0.3);
+        assert!(retry.availability > 0.99);
+        assert!(retry.retries > 0);
+        assert!(retry.latency.p99 > fail.latency.p99);
+    }
+
+    #[test]
+    fn node_outages_are_correlated() {
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 10], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_nodes(2)
+            .build();
+        let faults = FaultModel::new(Seconds(1.)).with_node_failures(Seconds(99.), Seconds(1.));
+
+        // Independent failures of the ten shard requests would fail about 10% of queries,
+        // but the shards only fail together with their two nodes.
+        let fail = simulation.simulate_find_with_faults(
+            0,
+            &faults,
+            CoordinatorPolicy::FailQuery,
+            20_000,
+            1,
+        );
+        assert!(fail.availability > 0.96 && fail.availability < 0.995);
+
+        // A replica on the other node is almost always up.
+        let retry = simulation.simulate_find_with_faults(
+            0,
+            &faults,
+            CoordinatorPolicy::RetryOnReplica { max_retries: 1 },
+            20_000,
+            1,
+        );
+        assert!(retry.availability > 0.999);
+
+        // Unless the placement puts both copies of every shard on the same node.
+        let index = simulation.index(0);
+        let placement = Placement {
+            assignments: index
+                .shard_ids()
+                .into_iter()
+                .flat_map(|shard_id| {
+                    let node = simulation.node_of(&index.shard(shard_id).unwrap());
+                    (0..2).map(move |replica| {
+                        let replica = Replica {
+                            index_id: 0,
+                            shard_id,
+                            replica,
+                        };
+                        (replica, node)
+                    })
+                })
+                .collect(),
+            unplaceable: Vec::new(),
+            utilization: Vec::new(),
+        };
+        let colocated = simulation.simulate_find_with_faults(
+            0,
+            &faults.clone().with_placement(&placement),
+            CoordinatorPolicy::RetryOnReplica { max_retries: 1 },
+            20_000,
+            1,
+        );
+        assert!(colocated.availability < 0.995);
+
+        // With a single node, replicas share the outage of their primary.
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 10], 768))
+            .build();
+        let retry = simulation.simulate_find_with_faults(
+            0,
+            &faults,
+            CoordinatorPolicy::RetryOnReplica { max_retries: 1 },
+            20_000,
+            1,
+        );
+        assert!(retry.availability < 0.995);
+    }
+
+    #[test]
+    fn unavailability_works() {
+        let faults = FaultModel::new(Seconds(1.)).with_node_failures(Seconds(99.), Seconds(1.));
+        assert!((faults.unavailability() - 0.01).abs() < 1e-12);
+    }
+}
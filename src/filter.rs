@@ -0,0 +1,259 @@
+use crate::simulation::{Simulation, SimulationResult};
+use crate::timing::Seconds;
+use rand::Rng;
+
+/// How a vector index evaluates the metadata filter of a query.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum FilterStrategy {
+    /// Filters are ignored and every vector is scanned.
+    #[default]
+    Unfiltered,
+    /// The predicate is evaluated for every vector first; only matching vectors are
+    /// searched.
+    PreFilter { predicate_cost: Seconds },
+    /// The index is searched without the filter for `k / selectivity` candidates, at
+    /// most `max_overfetch * k`, which are filtered afterwards. An unfiltered search for
+    /// `k` candidates visits `visited_fraction` of a shard.
+    PostFilter {
+        visited_fraction: f64,
+        max_overfetch: f64,
+    },
+    /// The predicate is evaluated while traversing the graph, skipping non-matching
+    /// vectors. The traversal visits `visited_fraction / selectivity` of a shard.
+    FilteredTraversal {
+        visited_fraction: f64,
+        predicate_cost: Seconds,
+    },
+}
+
+/// The work a filter strategy performs for a single query, relative to the size of a
+/// shard.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FilterPlan {
+    /// The fraction of vectors distances are computed for.
+    pub scanned_fraction: f64,
+    /// The fraction of vectors the predicate is evaluated for.
+    pub predicate_fraction: f64,
+    pub predicate_cost: Seconds,
+    /// The number of candidates fetched per requested result.
+    pub overfetch: f64,
+    /// The expected fraction of the requested `k` results that are returned.
+    pub completeness: f64,
+}
+
+/// The distribution of the fraction of vectors matching a query's filter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Selectivity {
+    Fixed(f64),
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    /// Selectivities spread evenly across orders of magnitude.
+    LogUniform {
+        min: f64,
+        max: f64,
+    },
+}
+
+#[derive(Debug)]
+pub struct FilteredResult {
+    pub result: SimulationResult,
+    pub plan: FilterPlan,
+}
+
+impl FilterPlan {
+    pub fn unfiltered() -> Self {
+        Self {
+            scanned_fraction: 1.,
+            predicate_fraction: 0.,
+            predicate_cost: Seconds(0.),
+            overfetch: 1.,
+            completeness: 1.,
+        }
+    }
+}
+
+impl FilterStrategy {
+    /// Plans the execution of a query whose filter matches `selectivity` of the vectors.
+    pub fn plan(&self, selectivity: f64) -> FilterPlan {
+        assert!(selectivity > 0. && selectivity <= 1.);
+        match *self {
+            FilterStrategy::Unfiltered => FilterPlan::unfiltered(),
+            FilterStrategy::PreFilter { predicate_cost } => FilterPlan {
+                scanned_fraction: selectivity,
+                predicate_fraction: 1.,
+                predicate_cost,
+                ..FilterPlan::unfiltered()
+            },
+            FilterStrategy::PostFilter {
+                visited_fraction,
+                max_overfetch,
+            } => {
+                let overfetch = (1. / selectivity).min(max_overfetch);
+                FilterPlan {
+                    scanned_fraction: (visited_fraction * overfetch).min(1.),
+                    overfetch,
+                    completeness: (overfetch * selectivity).min(1.),
+                    ..FilterPlan::unfiltered()
+                }
+            }
+            FilterStrategy::FilteredTraversal {
+                visited_fraction,
+                predicate_cost,
+            } => {
+                let visited = (visited_fraction / selectivity).min(1.);
+                FilterPlan {
+                    scanned_fraction: visited * selectivity,
+                    predicate_fraction: visited,
+                    predicate_cost,
+                    ..FilterPlan::unfiltered()
+                }
+            }
+        }
+    }
+}
+
+impl Selectivity {
+    pub fn uniform(min: f64, max: f64) -> Self {
+        let selectivity = Selectivity::Uniform { min, max };
+        selectivity.validate();
+        selectivity
+    }
+
+    pub fn log_uniform(min: f64, max: f64) -> Self {
+        let selectivity = Selectivity::LogUniform { min, max };
+        selectivity.validate();
+        selectivity
+    }
+
+    /// Panics unless every selectivity the distribution can produce lies in `(0, 1]`.
+    pub fn validate(&self) {
+        let (min, max) = match *self {
+            Selectivity::Fixed(selectivity) => (selectivity, selectivity),
+            Selectivity::Uniform { min, max } | Selectivity::LogUniform { min, max } => (min, max),
+        };
+        assert!(
+            0. < min && min <= max && max <= 1.,
+            "Selectivities must satisfy 0 < min <= max <= 1"
+        );
+    }
+
+    pub fn sample<R>(&self, rng: &mut R) -> f64
+    where
+        R: Rng,
+    {
+        self.quantile(rng.gen::<f64>())
+    }
+
+    /// The selectivity below which the given fraction `u` of queries falls.
+    pub fn quantile(&self, u: f64) -> f64 {
+        match *self {
+            Selectivity::Fixed(selectivity) => selectivity,
+            Selectivity::Uniform { min, max } => min + (max - min) * u,
+            Selectivity::LogUniform { min, max } => (min.ln() + (max.ln() - min.ln()) * u).exp(),
+        }
+    }
+}
+
+impl Simulation {
+    /// Like [`Simulation::simulate_find`], for a query whose filter matches `selectivity`
+    /// of the vectors, executed with the filter strategy of the index.
+    pub fn simulate_find_filtered(&self, index_id: usize, selectivity: f64) -> FilteredResult {
+        let index = self.index(index_id);
+        let plan = index.filter_strategy.plan(selectivity);
+        let shard_ids = self.routing.select_shards(index, None);
+        FilteredResult {
+            result: self.simulate_shards(index, &shard_ids, &plan),
+            plan,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::Nanoseconds;
+
+    fn simulation(strategy: FilterStrategy) -> Simulation {
+        SimulationBuilder::default()
+            .with_index(
+                Index::new_from_shards(0, &[1_000_000; 4], 128).with_filter_strategy(strategy),
+            )
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .build()
+    }
+
+    #[test]
+    fn pre_filter_scans_matching_vectors() {
+        let strategy = FilterStrategy::PreFilter {
+            predicate_cost: Nanoseconds(1.).into(),
+        };
+        let simulation = simulation(strategy);
+        let unfiltered = simulation.simulate_find(0).duration;
+        let filtered = simulation.simulate_find_filtered(0, 0.1);
+        // A tenth of the distance computations plus one predicate check per vector.
+        let expected = *unfiltered * 0.1 + 1e6 * 1e-9;
+        assert!((*filtered.result.duration - expected).abs() < 1e-9);
+        assert_eq!(filtered.result.recall, 1.);
+    }
+
+    #[test]
+    fn post_filter_overfetches_and_loses_results() {
+        let strategy = FilterStrategy::PostFilter {
+            visited_fraction: 0.01,
+            max_overfetch: 20.,
+        };
+        let plan = strategy.plan(0.1);
+        assert_eq!(plan.overfetch, 10.);
+        assert!((plan.scanned_fraction - 0.1).abs() < 1e-12);
+        assert_eq!(plan.completeness, 1.);
+
+        let result = simulation(strategy).simulate_find_filtered(0, 0.01).result;
+        assert!((result.recall - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn filtered_traversal_degrades_with_selectivity() {
+        let strategy = FilterStrategy::FilteredTraversal {
+            visited_fraction: 0.01,
+            predicate_cost: Nanoseconds(1.).into(),
+        };
+        let simulation = simulation(strategy);
+        let broad = simulation.simulate_find_filtered(0, 0.5).result.duration;
+        let narrow = simulation.simulate_find_filtered(0, 0.02).result.duration;
+        assert!(narrow > broad);
+        assert!(narrow < simulation.simulate_find(0).duration);
+    }
+
+    #[test]
+    #[should_panic(expected = "0 < min <= max <= 1")]
+    fn selectivity_ranges_exclude_zero() {
+        use crate::workload::Workload;
+
+        let selectivity = Selectivity::Uniform { min: 0., max: 0.5 };
+        Workload::new(Seconds(10.), 7).with_filtered_tenant(0, 20., selectivity);
+    }
+
+    #[test]
+    fn selective_workload_is_cheaper_with_pre_filter() {
+        use crate::workload::Workload;
+
+        let strategy = FilterStrategy::PreFilter {
+            predicate_cost: Nanoseconds(0.5).into(),
+        };
+        let simulation = simulation(strategy);
+        let selectivity = Selectivity::log_uniform(0.001, 0.1);
+        let unfiltered = Workload::new(Seconds(10.), 7).with_tenant(0, 20.);
+        let filtered = Workload::new(Seconds(10.), 7).with_filtered_tenant(0, 20., selectivity);
+
+        let unfiltered = simulation.simulate_workload(&unfiltered);
+        let filtered = simulation.simulate_workload(&filtered);
+        assert!(
+            filtered.tenant(0).unwrap().latency.mean < unfiltered.tenant(0).unwrap().latency.mean
+        );
+        assert!(filtered.utilization < unfiltered.utilization);
+    }
+}
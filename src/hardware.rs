@@ -0,0 +1,104 @@
+use crate::timing::Seconds;
+
+/// The size of a stored vector element in bytes; vectors are held as `f32`.
+pub const VECTOR_ELEMENT_BYTES: usize = 4;
+
+/// Describes the search capabilities of a class of nodes, e.g. an AVX-512 server
+/// or an ARM instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HardwareProfile {
+    pub name: String,
+    /// The time a single core spends on one vector element.
+    pub cost_per_vector_element: Seconds,
+    pub cores: usize,
+    /// The memory bandwidth of the node in bytes per second.
+    pub memory_bandwidth: f64,
+    /// The memory available for shards, in bytes.
+    pub ram_bytes: usize,
+    /// The price of running one node for an hour.
+    pub hourly_price: f64,
+}
+
+impl HardwareProfile {
+    pub fn new<N, C>(name: N, cost_per_vector_element: C, cores: usize) -> Self
+    where
+        N: Into<String>,
+        C: Into<Seconds>,
+    {
+        assert_ne!(cores, 0);
+        Self {
+            name: name.into(),
+            cost_per_vector_element: cost_per_vector_element.into(),
+            cores,
+            memory_bandwidth: f64::INFINITY,
+            ram_bytes: usize::MAX,
+            hourly_price: 0.,
+        }
+    }
+
+    pub fn with_memory(mut self, memory_bandwidth: f64, ram_bytes: usize) -> Self {
+        assert!(memory_bandwidth > 0.);
+        self.memory_bandwidth = memory_bandwidth;
+        self.ram_bytes = ram_bytes;
+        self
+    }
+
+    pub fn with_hourly_price(mut self, hourly_price: f64) -> Self {
+        assert!(hourly_price >= 0.);
+        self.hourly_price = hourly_price;
+        self
+    }
+
+    /// The number of vector elements the node can search per second using all cores,
+    /// limited by how fast they can be streamed from memory.
+    pub fn elements_per_second(&self) -> f64 {
+        let compute = self.cores as f64 / *self.cost_per_vector_element;
+        let memory = self.memory_bandwidth / VECTOR_ELEMENT_BYTES as f64;
+        compute.min(memory)
+    }
+}
+
+/// Sizes one shard per node such that every node needs the same time to search its
+/// shard, i.e. proportionally to the node's speed.
+pub fn proportional_shard_sizes(num_vectors: usize, profiles: &[HardwareProfile]) -> Vec<usize> {
+    assert!(!profiles.is_empty());
+    let speeds: Vec<f64> = profiles.iter().map(|p| p.elements_per_second()).collect();
+    let total: f64 = speeds.iter().sum();
+
+    let mut sizes: Vec<usize> = speeds
+        .iter()
+        .map(|speed| (num_vectors as f64 * speed / total).floor() as usize)
+        .collect();
+
+    // Hand the rounding remainder to the fastest nodes.
+    let mut by_speed: Vec<usize> = (0..profiles.len()).collect();
+    by_speed.sort_by(|&a, &b| speeds[b].total_cmp(&speeds[a]));
+    let remainder = num_vectors - sizes.iter().sum::<usize>();
+    for i in by_speed.into_iter().cycle().take(remainder) {
+        sizes[i] += 1;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Nanoseconds;
+
+    #[test]
+    fn memory_bandwidth_limits_throughput() {
+        let profile = HardwareProfile::new("avx2", Nanoseconds(0.2), 16);
+        assert!((profile.elements_per_second() - 8e10).abs() < 1.);
+        let profile = profile.with_memory(20e9, 64 << 30);
+        assert_eq!(profile.elements_per_second(), 5e9);
+    }
+
+    #[test]
+    fn proportional_sizes_follow_speed() {
+        let fast = HardwareProfile::new("avx512", Nanoseconds(0.1), 16);
+        let slow = HardwareProfile::new("arm", Nanoseconds(0.3), 16);
+        let sizes = proportional_shard_sizes(1_000_001, &[fast, slow]);
+        assert_eq!(sizes.iter().sum::<usize>(), 1_000_001);
+        assert_eq!(sizes, vec![750_001, 250_000]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::filter::FilterPlan;
+use crate::index::Index;
+use crate::simulation::{Simulation, SimulationResult};
+use crate::timing::Seconds;
+
+/// The cost of searching an inverted index, e.g. with BM25 scoring.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LexicalCostModel {
+    /// The fraction of a shard's documents that appear in the posting lists of a query.
+    pub match_fraction: f64,
+    /// The time spent scoring a single posting.
+    pub cost_per_posting: Seconds,
+    /// The fixed cost of a query on a shard, e.g. term dictionary lookups.
+    pub cost_per_shard: Seconds,
+}
+
+/// The lexical branch of a hybrid query, searched in parallel to the vector index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexicalIndex {
+    /// The number of documents in each shard. Every shard is searched on its own
+    /// node using a single thread.
+    pub shards: Vec<usize>,
+    pub cost: LexicalCostModel,
+}
+
+/// Merges the ranked results of both branches at the coordinator, e.g. using
+/// reciprocal rank fusion.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fusion {
+    /// The number of candidates each branch contributes.
+    pub candidates_per_branch: usize,
+    /// The time it takes to look up and score a single candidate.
+    pub cost_per_candidate: Seconds,
+}
+
+#[derive(Debug)]
+pub struct HybridResult {
+    /// The observed duration of the hybrid query, with both branches running in parallel.
+    pub duration: Seconds,
+    /// The total duration, as if everything executed sequentially.
+    pub duration_total: Seconds,
+    pub vector: SimulationResult,
+    pub lexical: Seconds,
+    pub fusion: Seconds,
+}
+
+impl LexicalIndex {
+    pub fn new_evenly_split(
+        num_documents: usize,
+        num_shards: usize,
+        cost: LexicalCostModel,
+    ) -> Self {
+        assert_ne!(num_shards, 0);
+        let shards = (0..num_shards)
+            .map(|i| num_documents / num_shards + usize::from(i < num_documents % num_shards))
+            .collect();
+        Self { shards, cost }
+    }
+
+    pub fn num_documents(&self) -> usize {
+        self.shards.iter().sum()
+    }
+
+    /// The time it takes to search each shard.
+    pub fn shard_search_times(&self) -> Vec<Seconds> {
+        self.shards
+            .iter()
+            .map(|&documents| {
+                let postings = (documents as f64 * self.cost.match_fraction).ceil() as usize;
+                self.cost.cost_per_shard + self.cost.cost_per_posting * postings
+            })
+            .collect()
+    }
+}
+
+impl Fusion {
+    pub fn duration(&self) -> Seconds {
+        self.cost_per_candidate * (2 * self.candidates_per_branch)
+    }
+}
+
+impl Simulation {
+    /// Simulates a hybrid query that searches the vector index and a lexical index in
+    /// parallel and fuses both result lists at the coordinator.
+    pub fn simulate_hybrid(
+        &self,
+        index_id: usize,
+        lexical: &LexicalIndex,
+        fusion: &Fusion,
+    ) -> HybridResult {
+        self.hybrid(self.index(index_id), lexical, fusion)
+    }
+
+    fn hybrid(&self, index: &Index, lexical: &LexicalIndex, fusion: &Fusion) -> HybridResult {
+        let shard_ids = self.routing.select_shards(index, None);
+        let vector = self.simulate_shards(index, &shard_ids, &FilterPlan::unfiltered());
+
+        // Lexical queries carry terms rather than a vector.
+        let (overhead, overhead_total) = self.scatter_gather_overhead(lexical.shards.len(), 0);
+        let search_times = lexical.shard_search_times();
+        let slowest = search_times
+            .iter()
+            .fold(Seconds(0.), |a, b| Seconds(a.0.max(b.0)));
+        let lexical_duration = slowest + overhead;
+        let lexical_total = search_times.into_iter().fold(overhead_total, |a, b| a + b);
+
+        let fusion_duration = fusion.duration();
+        HybridResult {
+            duration: Seconds(vector.duration.0.max(lexical_duration.0)) + fusion_duration,
+            duration_total: vector.duration_total + lexical_total + fusion_duration,
+            vector,
+            lexical: lexical_duration,
+            fusion: fusion_duration,
+        }
+    }
+
+    /// Re-shards the vector index into `1..=max_vector_shards` and the lexical index into
+    /// `1..=max_lexical_shards` evenly sized shards and returns the pair of shard counts
+    /// with the lowest simulated hybrid duration, along with its result.
+    pub fn optimal_hybrid_shard_counts(
+        &self,
+        index_id: usize,
+        max_vector_shards: usize,
+        lexical: &LexicalIndex,
+        max_lexical_shards: usize,
+        fusion: &Fusion,
+    ) -> (usize, usize, HybridResult) {
+        let index = self.index(index_id);
+        let mut best: Option<(usize, usize, HybridResult)> = None;
+        for vector_shards in 1..=max_vector_shards {
+            let candidate = Index::new_evenly_split(
+                index.index_id,
+                index.num_vectors,
+                vector_shards,
+                index.vector_length,
+            );
+            for lexical_shards in 1..=max_lexical_shards {
+                let lexical_candidate = LexicalIndex::new_evenly_split(
+                    lexical.num_documents(),
+                    lexical_shards,
+                    lexical.cost,
+                );
+                let result = self.hybrid(&candidate, &lexical_candidate, fusion);
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, _, b)| result.duration < b.duration)
+                {
+                    best = Some((vector_shards, lexical_shards, result));
+                }
+            }
+        }
+        best.expect("At least one shard is required")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Microseconds, Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 2], 128))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .build()
+    }
+
+    fn lexical(num_shards: usize) -> LexicalIndex {
+        let cost = LexicalCostModel {
+            match_fraction: 0.1,
+            cost_per_posting: Nanoseconds(50.).into(),
+            cost_per_shard: Microseconds(200.).into(),
+        };
+        LexicalIndex::new_evenly_split(10_000_000, num_shards, cost)
+    }
+
+    fn fusion() -> Fusion {
+        Fusion {
+            candidates_per_branch: 100,
+            cost_per_candidate: Microseconds(1.).into(),
+        }
+    }
+
+    #[test]
+    fn slower_branch_dominates() {
+        let simulation = simulation();
+        let result = simulation.simulate_hybrid(0, &lexical(1), &fusion());
+        // 1,000,000 postings at 50ns plus the shard overhead and one scatter-gather.
+        assert!((*result.lexical - (0.05 + 0.0002 + 0.002)).abs() < 1e-9);
+        assert!(result.lexical > result.vector.duration);
+        assert!((*result.duration - (*result.lexical + 0.0002)).abs() < 1e-9);
+
+        let result = simulation.simulate_hybrid(0, &lexical(4), &fusion());
+        assert!(result.lexical < result.vector.duration);
+        assert!((*result.duration - (*result.vector.duration + 0.0002)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn joint_sweep_balances_both_branches() {
+        let simulation = simulation();
+        let (vector_shards, lexical_shards, best) =
+            simulation.optimal_hybrid_shard_counts(0, 16, &lexical(1), 16, &fusion());
+        assert!(vector_shards > 1 && lexical_shards > 1);
+        for (v, l) in [(1, 1), (vector_shards, 1), (1, lexical_shards)] {
+            let index = Index::new_evenly_split(0, 2_000_000, v, 128);
+            let other = simulation.hybrid(&index, &lexical(l), &fusion());
+            assert!(best.duration <= other.duration);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::filter::FilterStrategy;
+use crate::segments::{SegmentConfig, SegmentLayout};
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::hash_map::Values;
 use std::collections::{BinaryHeap, HashMap};
@@ -6,13 +8,15 @@ use std::num::NonZeroUsize;
 
 pub type ShardId = NonZeroUsize;
 
-const SHARDID_ONE: ShardId = unsafe { NonZeroUsize::new_unchecked(1) };
+const SHARDID_ONE: ShardId = NonZeroUsize::new(1).unwrap();
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Index {
     pub index_id: usize,
     pub num_vectors: usize,
     pub vector_length: usize,
+    /// How metadata filters of queries against this index are evaluated.
+    pub filter_strategy: FilterStrategy,
     shards: HashMap<ShardId, RefCell<IndexAssignment>>,
     highest_shard_id: ShardId,
 }
@@ -25,6 +29,8 @@ impl Index {
             shard_id,
             num_vectors: num_items,
             vector_length,
+            centroid: None,
+            segments: None,
         };
         let mut shards = HashMap::new();
         shards.insert(shard_id, RefCell::new(first_shard));
@@ -33,6 +39,7 @@ impl Index {
             index_id,
             num_vectors: num_items,
             vector_length,
+            filter_strategy: FilterStrategy::default(),
             shards,
             highest_shard_id: shard_id,
         }
@@ -41,7 +48,7 @@ impl Index {
     pub fn new_from_shards(index_id: usize, num_items: &[usize], vector_length: usize) -> Self {
         // If no shards are specified, start with the root shard of zero items.
         if num_items.is_empty() {
-            Self::new(index_id, 0, vector_length);
+            return Self::new(index_id, 0, vector_length);
         }
 
         let mut shard_id = Self::root_shard_id();
@@ -50,17 +57,21 @@ impl Index {
             shard_id,
             num_vectors: num_items[0],
             vector_length,
+            centroid: None,
+            segments: None,
         };
         let mut shards = HashMap::new();
         shards.insert(shard_id, RefCell::new(first_shard));
 
-        for i in 1..num_items.len() {
+        for &num_vectors in &num_items[1..] {
             shard_id = shard_id.checked_add(1).unwrap();
             let next_shard = IndexAssignment {
                 index_id,
                 shard_id,
-                num_vectors: num_items[i],
+                num_vectors,
                 vector_length,
+                centroid: None,
+                segments: None,
             };
             shards.insert(shard_id, RefCell::new(next_shard));
         }
@@ -69,11 +80,31 @@ impl Index {
             index_id,
             num_vectors: num_items.iter().sum(),
             vector_length,
+            filter_strategy: FilterStrategy::default(),
             shards,
             highest_shard_id: shard_id,
         }
     }
 
+    pub fn with_filter_strategy(mut self, filter_strategy: FilterStrategy) -> Self {
+        self.filter_strategy = filter_strategy;
+        self
+    }
+
+    /// Splits `num_items` vectors as evenly as possible across `num_shards` shards.
+    pub fn new_evenly_split(
+        index_id: usize,
+        num_items: usize,
+        num_shards: usize,
+        vector_length: usize,
+    ) -> Self {
+        assert_ne!(num_shards, 0);
+        let shards: Vec<usize> = (0..num_shards)
+            .map(|i| num_items / num_shards + usize::from(i < num_items % num_shards))
+            .collect();
+        Self::new_from_shards(index_id, &shards, vector_length)
+    }
+
     pub fn weight(&self) -> usize {
         self.num_vectors * self.vector_length
     }
@@ -85,28 +116,38 @@ impl Index {
             shard_id,
             num_vectors: 0,
             vector_length: self.vector_length,
+            centroid: None,
+            segments: None,
         };
         self.shards.insert(shard_id, RefCell::new(assignment));
         shard_id
     }
 
-    pub fn shard(&self, shard_id: ShardId) -> Result<Ref<IndexAssignment>, GetShardError> {
+    pub fn shard(&self, shard_id: ShardId) -> Result<Ref<'_, IndexAssignment>, GetShardError> {
         if let Some(shard) = self.shards.get(&shard_id) {
             return Ok(shard.borrow());
         }
 
-        return Err(GetShardError::ShardNotFound { shard_id });
+        Err(GetShardError::ShardNotFound { shard_id })
     }
 
     pub fn get_shard_mut(
         &self,
         shard_id: ShardId,
-    ) -> Result<RefMut<IndexAssignment>, GetShardError> {
+    ) -> Result<RefMut<'_, IndexAssignment>, GetShardError> {
         if let Some(shard) = self.shards.get(&shard_id) {
             return Ok(shard.borrow_mut());
         }
 
-        return Err(GetShardError::ShardNotFound { shard_id });
+        Err(GetShardError::ShardNotFound { shard_id })
+    }
+
+    /// Assigns the routing centroid of a shard, enabling it to be pruned at query time.
+    pub fn set_centroid(&self, shard_id: ShardId, centroid: Vec<f32>) -> Result<(), GetShardError> {
+        let mut shard = self.get_shard_mut(shard_id)?;
+        assert_eq!(centroid.len(), self.vector_length);
+        shard.centroid = Some(centroid);
+        Ok(())
     }
 
     pub fn move_data(
@@ -114,7 +155,7 @@ impl Index {
         source_shard_id: ShardId,
         target_shard_id: ShardId,
         amount: usize,
-    ) -> Result<(Ref<IndexAssignment>, Ref<IndexAssignment>), AssignmentError> {
+    ) -> Result<(Ref<'_, IndexAssignment>, Ref<'_, IndexAssignment>), AssignmentError> {
         if !self.shards.contains_key(&source_shard_id) {
             return Err(AssignmentError::ShardNotFound {
                 shard_id: source_shard_id,
@@ -140,11 +181,70 @@ impl Index {
 
             source_shard.num_vectors -= amount;
             target_shard.num_vectors += amount;
+            if let Some(layout) = &mut source_shard.segments {
+                layout.remove(amount);
+            }
+            if let Some(layout) = &mut target_shard.segments {
+                layout.append(amount);
+            }
         }
 
         Ok((source_shard.borrow(), target_shard.borrow()))
     }
 
+    /// Adds newly inserted vectors to a shard.
+    pub fn insert(&mut self, shard_id: ShardId, amount: usize) -> Result<(), GetShardError> {
+        {
+            let mut shard = self.get_shard_mut(shard_id)?;
+            shard.num_vectors += amount;
+            if let Some(layout) = &mut shard.segments {
+                layout.append(amount);
+            }
+        }
+        self.num_vectors += amount;
+        Ok(())
+    }
+
+    /// Inserts vectors into a shard through its segment buffer, giving a shard without
+    /// a segment layout a fully compacted one first. Returns the number of vectors
+    /// written to segments.
+    pub fn insert_segmented(
+        &mut self,
+        shard_id: ShardId,
+        amount: usize,
+        config: &SegmentConfig,
+    ) -> Result<usize, GetShardError> {
+        let written = {
+            let mut shard = self.get_shard_mut(shard_id)?;
+            let num_vectors = shard.num_vectors;
+            shard.num_vectors += amount;
+            shard
+                .segments
+                .get_or_insert_with(|| config.initial_layout(num_vectors))
+                .insert(amount, config)
+        };
+        self.num_vectors += amount;
+        Ok(written)
+    }
+
+    /// Removes deleted vectors from a shard.
+    pub fn delete(&mut self, shard_id: ShardId, amount: usize) -> Result<(), AssignmentError> {
+        {
+            let mut shard = self
+                .get_shard_mut(shard_id)
+                .map_err(|_| AssignmentError::ShardNotFound { shard_id })?;
+            if shard.num_vectors < amount {
+                return Err(AssignmentError::SourceShardTooSmall);
+            }
+            shard.num_vectors -= amount;
+            if let Some(layout) = &mut shard.segments {
+                layout.remove(amount);
+            }
+        }
+        self.num_vectors -= amount;
+        Ok(())
+    }
+
     pub fn shard_ids(&self) -> Vec<ShardId> {
         self.shards
             .keys()
@@ -195,6 +295,12 @@ pub struct IndexAssignment {
     pub shard_id: ShardId,
     pub num_vectors: usize,
     pub vector_length: usize,
+    /// The centroid of the cluster held by this shard, if the index is semantically sharded.
+    /// Shards without a centroid cannot be pruned and are visited by every query.
+    pub centroid: Option<Vec<f32>>,
+    /// The segments the shard consists of, if its internals are modelled. Inserts and
+    /// moves through the [`Index`] append to its buffer, deletes shrink its segments.
+    pub segments: Option<SegmentLayout>,
 }
 
 impl IndexAssignment {
@@ -249,6 +355,50 @@ mod tests {
         assert_eq!(index.shard(ids[1]).unwrap().num_vectors, 75);
     }
 
+    #[test]
+    fn new_evenly_split_works() {
+        let index = Index::new_evenly_split(0, 10, 4, 512);
+        let sizes: Vec<usize> = index
+            .shard_ids()
+            .into_iter()
+            .map(|id| index.shard(id).unwrap().num_vectors)
+            .collect();
+        assert_eq!(sizes, vec![3, 3, 2, 2]);
+        assert_eq!(index.len(), 10);
+    }
+
+    #[test]
+    fn insert_and_delete_work() {
+        let mut index = Index::new_from_shards(0, &[50, 75], 512);
+        let ids = index.shard_ids();
+        index.insert(ids[0], 25).unwrap();
+        index.delete(ids[1], 70).unwrap();
+        assert!(index.delete(ids[1], 10).is_err());
+        assert_eq!(index.shard(ids[0]).unwrap().num_vectors, 75);
+        assert_eq!(index.shard(ids[1]).unwrap().num_vectors, 5);
+        assert_eq!(index.len(), 80);
+    }
+
+    #[test]
+    fn mutations_update_segment_layout() {
+        let mut index = Index::new_from_shards(0, &[50, 75], 512);
+        let ids = index.shard_ids();
+        index.get_shard_mut(ids[0]).unwrap().segments = Some(SegmentLayout::new(vec![50]));
+        index.get_shard_mut(ids[1]).unwrap().segments = Some(SegmentLayout::new(vec![75]));
+        let layout = |index: &Index, id| index.shard(id).unwrap().segments.clone().unwrap();
+
+        index.insert(ids[0], 10).unwrap();
+        assert_eq!(layout(&index, ids[0]).num_vectors(), 60);
+        assert_eq!(layout(&index, ids[0]).searched_segments(), 2);
+
+        index.delete(ids[0], 30).unwrap();
+        assert_eq!(layout(&index, ids[0]).num_vectors(), 30);
+
+        index.move_data(ids[1], ids[0], 5).unwrap();
+        assert_eq!(layout(&index, ids[0]).num_vectors(), 35);
+        assert_eq!(layout(&index, ids[1]).num_vectors(), 70);
+    }
+
     #[test]
     fn shard_assignment_works() {
         let mut index = Index::new(0, 100, 512);
@@ -0,0 +1,263 @@
+use crate::hardware::VECTOR_ELEMENT_BYTES;
+use crate::index::IndexAssignment;
+use crate::segments::SegmentConfig;
+use crate::simulation::Simulation;
+use crate::timing::Seconds;
+
+/// The CPU cost of inserting vectors into a shard.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WriteCostModel {
+    /// The time spent appending one byte to the write-ahead log.
+    pub wal_cost_per_byte: Seconds,
+    /// The time spent per graph level when inserting a vector into an HNSW graph;
+    /// an insert visits `log2(n)` levels for a shard of `n` vectors.
+    pub graph_insert_cost: Seconds,
+    /// How inserted vectors are flushed into segments and merged.
+    pub segments: SegmentConfig,
+    /// The time spent rewriting one vector during a merge.
+    pub merge_cost_per_vector: Seconds,
+}
+
+/// Inserts spread evenly over the shards of an index.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WriteWorkload {
+    pub inserts_per_second: f64,
+}
+
+#[derive(Debug)]
+pub struct MixedReport {
+    /// The mean search latency while writes are applied, or `None` if a node is
+    /// saturated by reads and writes.
+    pub latency: Option<Seconds>,
+    /// The mean search latency of the same read rate without writes.
+    pub read_only_latency: Option<Seconds>,
+    /// The fraction of each node's cores spent on writes.
+    pub write_utilization: Vec<f64>,
+    /// The fraction of each node's cores spent on searches.
+    pub read_utilization: Vec<f64>,
+    /// The highest insert rate the nodes can sustain without any reads.
+    pub max_inserts_per_second: f64,
+}
+
+impl WriteCostModel {
+    /// The CPU time a single insert costs a shard, including its amortized share of
+    /// segment merges.
+    pub fn insert_cost(&self, shard: &IndexAssignment) -> Seconds {
+        let bytes = shard.vector_length * VECTOR_ELEMENT_BYTES;
+        let levels = ((shard.num_vectors + 1) as f64).log2();
+        // The merges it took to ingest the shard's vectors through the segment buffer,
+        // beyond writing each of them once when it was flushed.
+        let written = self
+            .segments
+            .initial_layout(0)
+            .insert(shard.num_vectors, &self.segments);
+        let rewrites = (written as f64 / shard.num_vectors.max(1) as f64 - 1.).max(0.);
+        Seconds(
+            *self.wal_cost_per_byte * bytes as f64
+                + *self.graph_insert_cost * levels
+                + *self.merge_cost_per_vector * rewrites,
+        )
+    }
+
+    /// The CPU time it takes to build the graph of a shard from scratch.
+    pub fn build_cost(&self, shard: &IndexAssignment) -> Seconds {
+        let n = shard.num_vectors as f64;
+        Seconds(*self.graph_insert_cost * n * (n + 1.).log2())
+    }
+}
+
+impl Simulation {
+    /// The wall-clock time it takes to build every shard of an index, each using all
+    /// threads of its node. Shards on the same node are built one after another.
+    pub fn build_time(&self, index_id: usize, cost: &WriteCostModel) -> Seconds {
+        let mut per_node = vec![Seconds(0.); self.num_nodes];
+        for shard in self.index(index_id) {
+            let node = self.node_of(&shard);
+            per_node[node] += Seconds(*cost.build_cost(&shard) / self.node_threads(node) as f64);
+        }
+        per_node
+            .into_iter()
+            .fold(Seconds(0.), |a, b| Seconds(a.0.max(b.0)))
+    }
+
+    /// Estimates the search latency of an index serving reads and writes at the same
+    /// time. Writes are spread over every shard, while reads only visit the shards
+    /// selected by the routing. Each visited shard's search is slowed down by the share
+    /// of its node's cores that is busy with other reads and writes.
+    pub fn simulate_mixed(
+        &self,
+        index_id: usize,
+        writes: &WriteWorkload,
+        cost: &WriteCostModel,
+        queries_per_second: f64,
+    ) -> MixedReport {
+        let index = self.index(index_id);
+        let shard_ids = index.shard_ids();
+        let inserts_per_shard = writes.inserts_per_second / shard_ids.len() as f64;
+        let cores: Vec<f64> = (0..self.num_nodes)
+            .map(|node| match self.node_profiles().get(node) {
+                Some(profile) => profile.cores as f64,
+                None => self.thread_count as f64,
+            })
+            .collect();
+
+        let mut write_cpu = vec![0.; self.num_nodes];
+        for &shard_id in &shard_ids {
+            let shard = index.shard(shard_id).expect("Shard not found");
+            write_cpu[self.node_of(&shard)] += inserts_per_shard * *cost.insert_cost(&shard);
+        }
+
+        let routed = self.routing.select_shards(index, None);
+        let completion_times = self.shard_completion_times(index, &routed);
+        let mut read_cpu = vec![0.; self.num_nodes];
+        let mut shards = Vec::with_capacity(routed.len());
+        for (&shard_id, search_time) in routed.iter().zip(completion_times) {
+            let shard = index.shard(shard_id).expect("Shard not found");
+            let node = self.node_of(&shard);
+            read_cpu[node] += queries_per_second * *self.shard_search_time(&shard).1;
+            shards.push((node, search_time));
+        }
+        let (overhead, _) = self.scatter_gather_overhead(routed.len(), index.vector_length);
+
+        let write_utilization: Vec<f64> =
+            write_cpu.iter().zip(&cores).map(|(w, c)| w / c).collect();
+        let read_utilization: Vec<f64> = read_cpu.iter().zip(&cores).map(|(r, c)| r / c).collect();
+        let latency = |include_writes: bool| {
+            let mut slowest = Seconds(0.);
+            for &(node, search_time) in &shards {
+                let mut utilization = read_utilization[node];
+                if include_writes {
+                    utilization += write_utilization[node];
+                }
+                if utilization >= 1. {
+                    return None;
+                }
+                slowest = Seconds(slowest.0.max(*search_time / (1. - utilization)));
+            }
+            Some(slowest + overhead)
+        };
+
+        let max_inserts_per_second = write_utilization
+            .iter()
+            .filter(|&&u| u > 0.)
+            .map(|u| writes.inserts_per_second / u)
+            .fold(f64::INFINITY, f64::min);
+
+        MixedReport {
+            latency: latency(true),
+            read_only_latency: latency(false),
+            write_utilization,
+            read_utilization,
+            max_inserts_per_second,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::routing::RecallModel;
+    use crate::segments::CompactionPolicy;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Microseconds, Nanoseconds};
+
+    fn cost() -> WriteCostModel {
+        WriteCostModel {
+            wal_cost_per_byte: Nanoseconds(1.).into(),
+            graph_insert_cost: Microseconds(5.).into(),
+            segments: SegmentConfig::new(
+                10_000,
+                CompactionPolicy::Tiered {
+                    segments_per_tier: 10,
+                },
+            ),
+            merge_cost_per_vector: Microseconds(2.).into(),
+        }
+    }
+
+    fn simulation(num_shards: usize) -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_evenly_split(0, 1_000_000, num_shards, 128))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_threads(4, Nanoseconds(0.))
+            .with_nodes(num_shards)
+            .build()
+    }
+
+    #[test]
+    fn insert_cost_grows_with_shard_size() {
+        let cost = cost();
+        let small = Index::new(0, 100_000, 128);
+        let large = Index::new(0, 1_000_000, 128);
+        let small = cost.insert_cost(&small.shard(small.shard_ids()[0]).unwrap());
+        let large = cost.insert_cost(&large.shard(large.shard_ids()[0]).unwrap());
+        assert!(large > small);
+        // 512 bytes of WAL, 20 graph levels and two tiers of merges.
+        assert!((*large - (512e-9 + 5e-6 * 1_000_001f64.log2() + 4e-6)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ingest_inflates_search_latency() {
+        let simulation = simulation(4);
+        let writes = WriteWorkload {
+            inserts_per_second: 10_000.,
+        };
+        let report = simulation.simulate_mixed(0, &writes, &cost(), 10.);
+        let latency = report.latency.unwrap();
+        let read_only = report.read_only_latency.unwrap();
+        assert!(latency > read_only);
+        assert!(report.write_utilization.iter().all(|&u| u > 0. && u < 1.));
+
+        let writes = WriteWorkload {
+            inserts_per_second: report.max_inserts_per_second * 1.01,
+        };
+        assert!(simulation
+            .simulate_mixed(0, &writes, &cost(), 0.)
+            .latency
+            .is_none());
+    }
+
+    #[test]
+    fn merges_follow_the_compaction_policy() {
+        let index = Index::new(0, 1_000_000, 128);
+        let shard = index.shard(index.shard_ids()[0]).unwrap();
+        let tiered = cost();
+        let leveled = WriteCostModel {
+            segments: SegmentConfig::new(10_000, CompactionPolicy::Leveled { size_ratio: 10 }),
+            ..tiered
+        };
+        assert!(leveled.insert_cost(&shard) > tiered.insert_cost(&shard));
+    }
+
+    #[test]
+    fn reads_only_load_routed_shards() {
+        let index = Index::new_evenly_split(0, 1_000_000, 4, 2);
+        for (i, shard_id) in index.shard_ids().into_iter().enumerate() {
+            index.set_centroid(shard_id, vec![i as f32, 0.]).unwrap();
+        }
+        let simulation = SimulationBuilder::default()
+            .with_index(index)
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_threads(4, Nanoseconds(0.))
+            .with_nodes(4)
+            .with_shard_pruning(1, RecallModel::Uniform)
+            .build();
+        let writes = WriteWorkload {
+            inserts_per_second: 0.,
+        };
+        let report = simulation.simulate_mixed(0, &writes, &cost(), 1e-6);
+        assert_eq!(
+            report.read_utilization.iter().filter(|&&u| u > 0.).count(),
+            1
+        );
+        let latency = *report.read_only_latency.unwrap();
+        assert!((latency - *simulation.simulate_find(0).duration).abs() < 1e-9);
+    }
+
+    #[test]
+    fn more_shards_build_faster() {
+        let cost = cost();
+        assert!(simulation(8).build_time(0, &cost) < simulation(2).build_time(0, &cost));
+    }
+}
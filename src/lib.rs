@@ -0,0 +1,28 @@
+pub mod autoscaling;
+pub mod batching;
+pub mod cache;
+pub mod churn;
+pub mod cost;
+pub mod faults;
+pub mod filter;
+pub mod hardware;
+pub mod hybrid;
+pub mod index;
+pub mod ingest;
+pub mod load;
+pub mod network;
+pub mod placement;
+pub mod queueing;
+pub mod recall;
+pub mod routing;
+pub mod sampling;
+pub mod scaling;
+pub mod scheduler;
+pub mod segments;
+pub mod sensitivity;
+pub mod simulation;
+pub mod stats;
+pub mod tail;
+pub mod timing;
+pub mod topology;
+pub mod workload;
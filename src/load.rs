@@ -0,0 +1,340 @@
+use crate::index::{AssignmentError, Index, ShardId};
+use crate::simulation::Simulation;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+
+/// The traffic a shard received over an observation period.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ShardLoad {
+    pub queries_per_second: f64,
+    /// The CPU seconds per second spent searching the shard.
+    pub cpu_seconds: f64,
+}
+
+/// Per-shard load, keyed by index and shard ID.
+#[derive(Debug, Clone, Default)]
+pub struct LoadStatistics {
+    shards: HashMap<(usize, ShardId), ShardLoad>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoadImportError {
+    #[error("Line {line}: expected index_id,shard_id,queries_per_second,cpu_seconds")]
+    InvalidLine { line: usize },
+}
+
+/// What the rebalancer equalizes across the shards of an index.
+#[derive(Debug, Copy, Clone)]
+pub enum BalanceObjective<'a> {
+    /// Equalize the number of vectors per shard.
+    Size,
+    /// Equalize the CPU load per shard, assuming load is spread evenly over the
+    /// vectors within a shard.
+    Load(&'a LoadStatistics),
+}
+
+/// Vectors moved between two shards by the rebalancer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub source: ShardId,
+    pub target: ShardId,
+    pub amount: usize,
+}
+
+/// How unevenly size and load are spread over the shards of a layout, each given as
+/// the ratio of the largest shard to the mean.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LayoutBalance {
+    pub size_imbalance: f64,
+    pub load_imbalance: f64,
+}
+
+#[derive(Debug)]
+pub struct LayoutComparison {
+    pub current: LayoutBalance,
+    pub size_balanced: LayoutBalance,
+    pub load_balanced: LayoutBalance,
+}
+
+impl LoadStatistics {
+    pub fn record(&mut self, index_id: usize, shard_id: ShardId, load: ShardLoad) {
+        let entry = self.shards.entry((index_id, shard_id)).or_default();
+        entry.queries_per_second += load.queries_per_second;
+        entry.cpu_seconds += load.cpu_seconds;
+    }
+
+    pub fn get(&self, index_id: usize, shard_id: ShardId) -> ShardLoad {
+        self.shards
+            .get(&(index_id, shard_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Parses statistics exported from production, one shard per line in the form
+    /// `index_id,shard_id,queries_per_second,cpu_seconds`. Empty lines and lines starting
+    /// with `#` are skipped.
+    pub fn import(csv: &str) -> Result<Self, LoadImportError> {
+        let mut statistics = Self::default();
+        for (i, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || LoadImportError::InvalidLine { line: i + 1 };
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != 4 {
+                return Err(invalid());
+            }
+            let index_id = fields[0].parse().map_err(|_| invalid())?;
+            let shard_id = fields[1].parse::<NonZeroUsize>().map_err(|_| invalid())?;
+            let queries_per_second = fields[2].parse().map_err(|_| invalid())?;
+            let cpu_seconds = fields[3].parse().map_err(|_| invalid())?;
+            statistics.record(
+                index_id,
+                shard_id,
+                ShardLoad {
+                    queries_per_second,
+                    cpu_seconds,
+                },
+            );
+        }
+        Ok(statistics)
+    }
+}
+
+impl Simulation {
+    /// Routes the given queries, issued uniformly over `duration_seconds`, and records
+    /// the traffic and CPU time each shard of the index receives.
+    pub fn collect_load(
+        &self,
+        index_id: usize,
+        queries: &[Vec<f32>],
+        duration_seconds: f64,
+    ) -> LoadStatistics {
+        let index = self.index(index_id);
+        let mut statistics = LoadStatistics::default();
+        for query in queries {
+            for shard_id in self.routing.select_shards(index, Some(query)) {
+                let shard = index.shard(shard_id).expect("Shard not found");
+                let (_, cpu_time) = self.shard_search_time(&shard);
+                statistics.record(
+                    index_id,
+                    shard_id,
+                    ShardLoad {
+                        queries_per_second: 1. / duration_seconds,
+                        cpu_seconds: *cpu_time / duration_seconds,
+                    },
+                );
+            }
+        }
+        statistics
+    }
+}
+
+struct ShardState {
+    shard_id: ShardId,
+    vectors: usize,
+    load: f64,
+}
+
+impl ShardState {
+    fn density(&self) -> f64 {
+        if self.vectors == 0 {
+            0.
+        } else {
+            self.load / self.vectors as f64
+        }
+    }
+}
+
+fn shard_states(index: &Index, statistics: Option<&LoadStatistics>) -> Vec<ShardState> {
+    index
+        .shard_ids()
+        .into_iter()
+        .map(|shard_id| {
+            let vectors = index.shard(shard_id).unwrap().num_vectors;
+            let load = match statistics {
+                Some(statistics) => statistics.get(index.index_id, shard_id).cpu_seconds,
+                None => vectors as f64,
+            };
+            ShardState {
+                shard_id,
+                vectors,
+                load,
+            }
+        })
+        .collect()
+}
+
+fn imbalance(values: impl Iterator<Item = f64>) -> f64 {
+    let values: Vec<f64> = values.collect();
+    let mean = values.iter().sum::<f64>() / values.len().max(1) as f64;
+    if mean <= 0. {
+        return 1.;
+    }
+    values.iter().cloned().fold(0., f64::max) / mean
+}
+
+/// Moves vectors between the shards of an index until the objective is balanced to
+/// within `tolerance` (e.g. `1.05` for the largest shard exceeding the mean by 5 %).
+pub fn rebalance(
+    index: &Index,
+    objective: BalanceObjective,
+    tolerance: f64,
+) -> Result<Vec<Move>, AssignmentError> {
+    let statistics = match objective {
+        BalanceObjective::Size => None,
+        BalanceObjective::Load(statistics) => Some(statistics),
+    };
+    let mut shards = shard_states(index, statistics);
+    let mut moves = Vec::new();
+
+    for _ in 0..shards.len() * 16 {
+        if imbalance(shards.iter().map(|s| s.load)) <= tolerance {
+            break;
+        }
+
+        let hottest = (0..shards.len())
+            .max_by(|&a, &b| shards[a].load.total_cmp(&shards[b].load))
+            .unwrap();
+        let coldest = (0..shards.len())
+            .min_by(|&a, &b| shards[a].load.total_cmp(&shards[b].load))
+            .unwrap();
+
+        let density = shards[hottest].density();
+        if density <= 0. {
+            break;
+        }
+        let gap = (shards[hottest].load - shards[coldest].load) / 2.;
+        let amount = ((gap / density).round() as usize).min(shards[hottest].vectors);
+        if amount == 0 {
+            break;
+        }
+
+        index.move_data(shards[hottest].shard_id, shards[coldest].shard_id, amount)?;
+        let moved_load = amount as f64 * density;
+        shards[hottest].vectors -= amount;
+        shards[hottest].load -= moved_load;
+        shards[coldest].vectors += amount;
+        shards[coldest].load += moved_load;
+        moves.push(Move {
+            source: shards[hottest].shard_id,
+            target: shards[coldest].shard_id,
+            amount,
+        });
+    }
+
+    Ok(moves)
+}
+
+/// Measures how balanced the size and the load of an index are. The load is taken from
+/// the statistics and assumed to move along with the vectors.
+pub fn layout_balance(index: &Index, statistics: &LoadStatistics) -> LayoutBalance {
+    let shards = shard_states(index, Some(statistics));
+    LayoutBalance {
+        size_imbalance: imbalance(shards.iter().map(|s| s.vectors as f64)),
+        load_imbalance: imbalance(shards.iter().map(|s| s.load)),
+    }
+}
+
+/// Rebalances copies of the index by size and by load and reports how balanced each
+/// resulting layout is in terms of both size and load.
+pub fn compare_layouts(
+    index: &Index,
+    statistics: &LoadStatistics,
+    tolerance: f64,
+) -> Result<LayoutComparison, AssignmentError> {
+    let evaluate = |objective: BalanceObjective| -> Result<LayoutBalance, AssignmentError> {
+        let candidate = index.clone();
+        let mut shards = shard_states(&candidate, Some(statistics));
+        for m in rebalance(&candidate, objective, tolerance)? {
+            let source = shards.iter().position(|s| s.shard_id == m.source).unwrap();
+            let target = shards.iter().position(|s| s.shard_id == m.target).unwrap();
+            let moved_load = m.amount as f64 * shards[source].density();
+            shards[source].vectors -= m.amount;
+            shards[source].load -= moved_load;
+            shards[target].vectors += m.amount;
+            shards[target].load += moved_load;
+        }
+        Ok(LayoutBalance {
+            size_imbalance: imbalance(shards.iter().map(|s| s.vectors as f64)),
+            load_imbalance: imbalance(shards.iter().map(|s| s.load)),
+        })
+    };
+
+    Ok(LayoutComparison {
+        current: layout_balance(index, statistics),
+        size_balanced: evaluate(BalanceObjective::Size)?,
+        load_balanced: evaluate(BalanceObjective::Load(statistics))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hot_statistics(index: &Index) -> LoadStatistics {
+        let csv = index
+            .shard_ids()
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| format!("0,{id},10,{}", if i == 0 { 8. } else { 1. }))
+            .collect::<Vec<_>>()
+            .join("\n");
+        LoadStatistics::import(&csv).unwrap()
+    }
+
+    #[test]
+    fn import_works() {
+        let statistics = LoadStatistics::import("# comment\n0,1,12.5,0.75\n\n1,2,3,0.1").unwrap();
+        let shard_id = NonZeroUsize::new(1).unwrap();
+        assert_eq!(statistics.get(0, shard_id).queries_per_second, 12.5);
+        assert_eq!(statistics.get(0, shard_id).cpu_seconds, 0.75);
+        assert!(LoadStatistics::import("0,1,x,1").is_err());
+    }
+
+    #[test]
+    fn size_rebalancing_equalizes_vectors() {
+        let index = Index::new_from_shards(0, &[900, 100, 200], 8);
+        rebalance(&index, BalanceObjective::Size, 1.01).unwrap();
+        for shard in &index {
+            assert!(shard.num_vectors.abs_diff(400) <= 4);
+        }
+    }
+
+    #[test]
+    fn load_balancing_beats_size_balancing_on_load() {
+        let index = Index::new_from_shards(0, &[1000; 4], 8);
+        let statistics = hot_statistics(&index);
+        let comparison = compare_layouts(&index, &statistics, 1.05).unwrap();
+
+        assert_eq!(comparison.size_balanced, comparison.current);
+        assert!(comparison.current.load_imbalance > 2.);
+        assert!(comparison.load_balanced.load_imbalance <= 1.05);
+        assert!(comparison.load_balanced.size_imbalance > 1.);
+    }
+
+    #[test]
+    fn load_is_collected_from_routed_queries() {
+        use crate::routing::RecallModel;
+        use crate::simulation::SimulationBuilder;
+        use crate::timing::Nanoseconds;
+
+        let index = Index::new_from_shards(0, &[1000; 2], 2);
+        let ids = index.shard_ids();
+        index.set_centroid(ids[0], vec![0., 0.]).unwrap();
+        index.set_centroid(ids[1], vec![10., 10.]).unwrap();
+        let simulation = SimulationBuilder::default()
+            .with_index(index)
+            .with_search_cost(Nanoseconds(1.), Nanoseconds(0.))
+            .with_shard_pruning(1, RecallModel::Uniform)
+            .build();
+
+        let queries = vec![vec![0., 1.], vec![1., 0.], vec![9., 9.]];
+        let statistics = simulation.collect_load(0, &queries, 1.);
+        assert_eq!(statistics.get(0, ids[0]).queries_per_second, 2.);
+        assert_eq!(statistics.get(0, ids[1]).queries_per_second, 1.);
+        assert!((statistics.get(0, ids[0]).cpu_seconds - 4e-6).abs() < 1e-12);
+    }
+}
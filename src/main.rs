@@ -1,27 +1,48 @@
-extern crate core;
+use balancing_rs::index::Index;
+use balancing_rs::sensitivity::{sweep, DurationModel, RunInputs, ELEMENT_COST};
+use balancing_rs::simulation::SimulationBuilder;
 
-mod index;
-mod simulation;
-mod timing;
+pub fn main() {
+    let sweep = sweep(2000, 0);
+    let points = sweep.points();
 
-use crate::simulation::SimulationBuilder;
-use crate::timing::{Microseconds, Milliseconds, Nanoseconds};
-use index::Index;
-use rand::{thread_rng, Rng};
+    // The design and its coverage go to stderr to keep stdout a plain CSV.
+    eprintln!("# runs={}", sweep.len());
+    for (axis, coverage) in sweep.axes.iter().zip(&points.coverage.axis_coverage) {
+        eprintln!(
+            "# axis={} min={} max={} scale={:?} sampler={:?} coverage={coverage:.3}",
+            axis.name, axis.min, axis.max, axis.scale, axis.sampler
+        );
+    }
+    eprintln!("# pair_coverage={:.3}", points.coverage.pair_coverage);
+    eprintln!("# min_distance={:.5}", points.coverage.min_distance);
 
-pub fn main() {
-    let mut rng = thread_rng();
+    // Which axes drive the duration of a single evenly split index.
+    let report = DurationModel::new(784).analyze(20, 256, 0);
+    eprintln!("# duration_variance={:.5}", report.variance);
+    for sensitivity in &report.parameters {
+        eprintln!(
+            "# sensitivity={:?} total={:.3} first_order={:.3} mu_star={:.5} sigma={:.5}",
+            sensitivity.parameter,
+            sensitivity.sobol.total,
+            sensitivity.sobol.first_order,
+            sensitivity.morris.mu_star,
+            sensitivity.morris.sigma
+        );
+    }
 
     println!("row,run,num_shards,num_threads,num_dims,num_vectors,weight,cost_per_vector,cost_per_scatter,cost_per_gather,thread_overhead,duration,total_duration");
     let mut row_id: usize = 0;
-    for run in 0..2000 {
-        let num_threads: usize = rng.gen_range(1..32);
-        let num_shards: usize = rng.gen_range(1..40);
-        let num_elements: usize = rng.gen_range(10..1_000_000);
-        let search_cost_per_vector = Nanoseconds(rng.gen::<f64>() * 50.);
-        let search_cost_per_scatter = Milliseconds(rng.gen::<f64>() * 100.);
-        let search_cost_per_gather = Milliseconds(rng.gen::<f64>() * 100.);
-        let thread_overhead = Microseconds(rng.gen::<f64>() * 100.);
+    for (run, point) in points.values.iter().enumerate() {
+        let RunInputs {
+            num_threads,
+            num_shards,
+            num_elements,
+            cost_per_vector: search_cost_per_vector,
+            cost_per_scatter: search_cost_per_scatter,
+            cost_per_gather: search_cost_per_gather,
+            thread_overhead,
+        } = RunInputs::new(&sweep.axes, point);
 
         let dyn_shards_hi = vec![20_000_000 / num_shards; num_shards];
         let dyn_shards_lo = vec![100 / num_shards; num_shards];
@@ -58,7 +79,7 @@ pub fn main() {
             .with_index(Index::new_from_shards(19, &dyn_shards_hi, 786))
             .with_index(Index::new_from_shards(20, &dyn_shards_lo, 786))
             .with_index(Index::new_from_shards(21, &dyn_shards_dyn, 786))
-            .with_search_cost(Nanoseconds(0.171326754), search_cost_per_vector)
+            .with_search_cost(ELEMENT_COST, search_cost_per_vector)
             .with_scatter_gather_cost(search_cost_per_scatter, search_cost_per_gather)
             .with_threads(num_threads, thread_overhead)
             .build();
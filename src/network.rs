@@ -0,0 +1,128 @@
+use crate::timing::Seconds;
+
+/// The scalar type vectors are transmitted as.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ElementType {
+    #[default]
+    F32,
+    F16,
+    I8,
+}
+
+impl ElementType {
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            ElementType::F32 => 4,
+            ElementType::F16 => 2,
+            ElementType::I8 => 1,
+        }
+    }
+}
+
+/// A network link between two hosts.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Link {
+    /// The one-way latency of a message.
+    pub latency: Seconds,
+    /// The link throughput in bytes per second.
+    pub bandwidth: f64,
+}
+
+impl Link {
+    pub fn new<L>(latency: L, bandwidth: f64) -> Self
+    where
+        L: Into<Seconds>,
+    {
+        assert!(bandwidth > 0.);
+        Self {
+            latency: latency.into(),
+            bandwidth,
+        }
+    }
+
+    /// The time it takes to deliver a message of the given size.
+    pub fn transfer(&self, bytes: usize) -> Seconds {
+        self.latency + Seconds(bytes as f64 / self.bandwidth)
+    }
+}
+
+/// Derives request and response transfer times from the payload sizes of a query.
+///
+/// Children of every scatter level are spread round-robin across the configured tiers,
+/// e.g. the same rack, another rack and another zone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkModel {
+    pub tiers: Vec<Link>,
+    pub element_type: ElementType,
+    /// The number of results each child returns.
+    pub top_k: usize,
+}
+
+/// The size of a single result entry: a 64-bit vector ID and a 32-bit score.
+const RESULT_ENTRY_BYTES: usize = 12;
+
+impl NetworkModel {
+    pub fn new(link: Link) -> Self {
+        Self {
+            tiers: vec![link],
+            element_type: ElementType::default(),
+            top_k: 10,
+        }
+    }
+
+    /// Adds a further, typically slower, tier such as a cross-rack or cross-zone link.
+    pub fn with_tier(mut self, link: Link) -> Self {
+        self.tiers.push(link);
+        self
+    }
+
+    pub fn with_payload(mut self, element_type: ElementType, top_k: usize) -> Self {
+        self.element_type = element_type;
+        self.top_k = top_k;
+        self
+    }
+
+    pub fn query_bytes(&self, vector_length: usize) -> usize {
+        vector_length * self.element_type.size_in_bytes()
+    }
+
+    pub fn response_bytes(&self) -> usize {
+        self.top_k * RESULT_ENTRY_BYTES
+    }
+
+    /// The link used to reach the `child`-th child of a node.
+    pub fn link(&self, child: usize) -> &Link {
+        &self.tiers[child % self.tiers.len()]
+    }
+
+    /// The time spent on the wire to send a query to the `child`-th child and receive
+    /// its results.
+    pub fn round_trip(&self, child: usize, vector_length: usize) -> Seconds {
+        let link = self.link(child);
+        link.transfer(self.query_bytes(vector_length)) + link.transfer(self.response_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Microseconds;
+
+    #[test]
+    fn payload_sizes_work() {
+        let network = NetworkModel::new(Link::new(Microseconds(50.), 1.25e9))
+            .with_payload(ElementType::F16, 100);
+        assert_eq!(network.query_bytes(768), 1536);
+        assert_eq!(network.response_bytes(), 1200);
+    }
+
+    #[test]
+    fn tiers_are_assigned_round_robin() {
+        let local = Link::new(Microseconds(50.), 1.25e9);
+        let remote = Link::new(Microseconds(500.), 1.25e8);
+        let network = NetworkModel::new(local).with_tier(remote);
+        assert_eq!(network.link(0), &local);
+        assert_eq!(network.link(3), &remote);
+        assert!(network.round_trip(1, 768) > network.round_trip(0, 768));
+    }
+}
@@ -0,0 +1,333 @@
+use crate::hardware::{HardwareProfile, VECTOR_ELEMENT_BYTES};
+use crate::index::ShardId;
+use crate::simulation::Simulation;
+use std::collections::HashMap;
+
+/// The algorithm used to assign shard replicas to nodes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PlacementStrategy {
+    /// Place the largest replicas first, each on the first node it fits on.
+    #[default]
+    FirstFitDecreasing,
+    /// Place the largest replicas first, each on the node it fills up the most.
+    BestFitDecreasing,
+    /// Search all assignments for one that leaves the fewest replicas unplaced.
+    /// The search is exponential and only suited for small clusters.
+    BranchAndBound,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlacementRequest {
+    pub strategy: PlacementStrategy,
+    /// The number of copies of every shard; copies must be placed on distinct nodes.
+    pub replication_factor: usize,
+    /// The query rate of every index, spread evenly over the replicas of each shard.
+    pub queries_per_second: f64,
+    /// The fraction of a node's cores that may be used for searching.
+    pub max_cpu_utilization: f64,
+}
+
+/// A single copy of a shard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Replica {
+    pub index_id: usize,
+    pub shard_id: ShardId,
+    pub replica: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct NodeUtilization {
+    /// The fraction of the node's memory occupied by shards.
+    pub memory: f64,
+    /// The fraction of the node's cores busy searching.
+    pub cpu: f64,
+}
+
+#[derive(Debug)]
+pub struct Placement {
+    /// The node each placed replica was assigned to.
+    pub assignments: HashMap<Replica, usize>,
+    /// The replicas that could not be placed without violating a constraint.
+    pub unplaceable: Vec<Replica>,
+    pub utilization: Vec<NodeUtilization>,
+}
+
+struct Item {
+    replica: Replica,
+    memory: usize,
+    /// The CPU seconds per second the replica needs on each node.
+    cpu: Vec<f64>,
+}
+
+struct Nodes<'a> {
+    profiles: &'a [HardwareProfile],
+    max_cpu_utilization: f64,
+    memory_used: Vec<usize>,
+    cpu_used: Vec<f64>,
+    shards: Vec<Vec<(usize, ShardId)>>,
+}
+
+impl<'a> Nodes<'a> {
+    fn new(profiles: &'a [HardwareProfile], max_cpu_utilization: f64) -> Self {
+        Self {
+            profiles,
+            max_cpu_utilization,
+            memory_used: vec![0; profiles.len()],
+            cpu_used: vec![0.; profiles.len()],
+            shards: vec![Vec::new(); profiles.len()],
+        }
+    }
+
+    fn fits(&self, node: usize, item: &Item) -> bool {
+        let profile = &self.profiles[node];
+        let shard = (item.replica.index_id, item.replica.shard_id);
+        self.memory_used[node] + item.memory <= profile.ram_bytes
+            && self.cpu_used[node] + item.cpu[node]
+                <= profile.cores as f64 * self.max_cpu_utilization
+            && !self.shards[node].contains(&shard)
+    }
+
+    fn free_memory_after(&self, node: usize, item: &Item) -> usize {
+        self.profiles[node].ram_bytes - self.memory_used[node] - item.memory
+    }
+
+    fn add(&mut self, node: usize, item: &Item) {
+        self.memory_used[node] += item.memory;
+        self.cpu_used[node] += item.cpu[node];
+        self.shards[node].push((item.replica.index_id, item.replica.shard_id));
+    }
+
+    fn remove(&mut self, node: usize, item: &Item) {
+        self.memory_used[node] -= item.memory;
+        self.cpu_used[node] -= item.cpu[node];
+        self.shards[node].pop();
+    }
+
+    fn utilization(&self) -> Vec<NodeUtilization> {
+        self.profiles
+            .iter()
+            .enumerate()
+            .map(|(node, profile)| NodeUtilization {
+                memory: self.memory_used[node] as f64 / profile.ram_bytes as f64,
+                cpu: self.cpu_used[node] / profile.cores as f64,
+            })
+            .collect()
+    }
+}
+
+impl Simulation {
+    /// Assigns the replicas of every shard of every index to the simulation's nodes,
+    /// respecting each node's memory and CPU capacity and keeping replicas of the same
+    /// shard on different nodes.
+    pub fn place(&self, request: &PlacementRequest) -> Placement {
+        let profiles = self.node_profiles();
+        assert!(!profiles.is_empty(), "Placement requires node profiles");
+        assert_ne!(request.replication_factor, 0);
+
+        let per_replica_qps = request.queries_per_second / request.replication_factor as f64;
+        let mut items = Vec::new();
+        for index_id in self.index_id() {
+            for shard in self.index(index_id) {
+                for replica in 0..request.replication_factor {
+                    let cpu = profiles
+                        .iter()
+                        .map(|profile| {
+                            let per_vector = profile.cost_per_vector_element * shard.vector_length
+                                + self.search_cost_per_vector;
+                            per_replica_qps * *(per_vector * shard.num_vectors)
+                        })
+                        .collect();
+                    items.push(Item {
+                        replica: Replica {
+                            index_id,
+                            shard_id: shard.shard_id,
+                            replica,
+                        },
+                        memory: shard.num_vectors * shard.vector_length * VECTOR_ELEMENT_BYTES,
+                        cpu,
+                    });
+                }
+            }
+        }
+        items.sort_by(|a, b| b.memory.cmp(&a.memory).then(a.replica.cmp(&b.replica)));
+
+        let mut nodes = Nodes::new(profiles, request.max_cpu_utilization);
+        let choice = match request.strategy {
+            PlacementStrategy::FirstFitDecreasing => greedy(&items, &mut nodes, |nodes, item| {
+                (0..nodes.profiles.len()).find(|&node| nodes.fits(node, item))
+            }),
+            PlacementStrategy::BestFitDecreasing => greedy(&items, &mut nodes, |nodes, item| {
+                (0..nodes.profiles.len())
+                    .filter(|&node| nodes.fits(node, item))
+                    .min_by_key(|&node| nodes.free_memory_after(node, item))
+            }),
+            PlacementStrategy::BranchAndBound => {
+                let mut best = greedy(
+                    &items,
+                    &mut Nodes::new(profiles, request.max_cpu_utilization),
+                    |nodes, item| (0..nodes.profiles.len()).find(|&node| nodes.fits(node, item)),
+                );
+                let mut current = Vec::with_capacity(items.len());
+                branch_and_bound(&items, &mut nodes, &mut current, 0, &mut best);
+
+                nodes = Nodes::new(profiles, request.max_cpu_utilization);
+                for (item, node) in items.iter().zip(&best) {
+                    if let Some(node) = node {
+                        nodes.add(*node, item);
+                    }
+                }
+                best
+            }
+        };
+
+        let mut placement = Placement {
+            assignments: HashMap::new(),
+            unplaceable: Vec::new(),
+            utilization: nodes.utilization(),
+        };
+        for (item, node) in items.iter().zip(choice) {
+            match node {
+                Some(node) => {
+                    placement.assignments.insert(item.replica, node);
+                }
+                None => placement.unplaceable.push(item.replica),
+            }
+        }
+        placement.unplaceable.sort();
+        placement
+    }
+}
+
+fn greedy<F>(items: &[Item], nodes: &mut Nodes, mut choose: F) -> Vec<Option<usize>>
+where
+    F: FnMut(&Nodes, &Item) -> Option<usize>,
+{
+    items
+        .iter()
+        .map(|item| {
+            let node = choose(nodes, item);
+            if let Some(node) = node {
+                nodes.add(node, item);
+            }
+            node
+        })
+        .collect()
+}
+
+fn unplaced(choice: &[Option<usize>]) -> usize {
+    choice.iter().filter(|node| node.is_none()).count()
+}
+
+fn branch_and_bound(
+    items: &[Item],
+    nodes: &mut Nodes,
+    current: &mut Vec<Option<usize>>,
+    position: usize,
+    best: &mut Vec<Option<usize>>,
+) {
+    let best_unplaced = unplaced(best);
+    if best_unplaced == 0 || unplaced(current) >= best_unplaced {
+        return;
+    }
+    if position == items.len() {
+        *best = current.clone();
+        return;
+    }
+
+    let item = &items[position];
+    let mut tried_empty = false;
+    for node in 0..nodes.profiles.len() {
+        if !nodes.fits(node, item) {
+            continue;
+        }
+        // Empty nodes of identical hardware are interchangeable.
+        let is_empty = nodes.memory_used[node] == 0;
+        if is_empty && tried_empty && all_profiles_equal(nodes.profiles) {
+            continue;
+        }
+        tried_empty |= is_empty;
+
+        nodes.add(node, item);
+        current.push(Some(node));
+        branch_and_bound(items, nodes, current, position + 1, best);
+        current.pop();
+        nodes.remove(node, item);
+    }
+
+    current.push(None);
+    branch_and_bound(items, nodes, current, position + 1, best);
+    current.pop();
+}
+
+fn all_profiles_equal(profiles: &[HardwareProfile]) -> bool {
+    profiles.windows(2).all(|w| w[0] == w[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::Nanoseconds;
+
+    fn simulation(shards: &[usize], ram_bytes: usize, nodes: usize) -> Simulation {
+        let profile =
+            HardwareProfile::new("avx2", Nanoseconds(0.17), 16).with_memory(20e9, ram_bytes);
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, shards, 1))
+            .with_search_cost(Nanoseconds(0.17), Nanoseconds(0.))
+            .with_node_profiles(vec![profile; nodes])
+            .build()
+    }
+
+    fn request(strategy: PlacementStrategy, replication_factor: usize) -> PlacementRequest {
+        PlacementRequest {
+            strategy,
+            replication_factor,
+            queries_per_second: 1.,
+            max_cpu_utilization: 0.8,
+        }
+    }
+
+    #[test]
+    fn replicas_are_spread_across_nodes() {
+        let simulation = simulation(&[100; 3], 1500, 3);
+        let placement = simulation.place(&request(PlacementStrategy::FirstFitDecreasing, 3));
+        assert!(placement.unplaceable.is_empty());
+        for shard_id in simulation.index(0).shard_ids() {
+            let mut nodes: Vec<usize> = placement
+                .assignments
+                .iter()
+                .filter(|(replica, _)| replica.shard_id == shard_id)
+                .map(|(_, node)| *node)
+                .collect();
+            nodes.sort();
+            assert_eq!(nodes, vec![0, 1, 2]);
+        }
+        assert!((placement.utilization[0].memory - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unplaceable_replicas_are_reported() {
+        let simulation = simulation(&[100; 2], 1000, 1);
+        let placement = simulation.place(&request(PlacementStrategy::BestFitDecreasing, 2));
+        assert_eq!(placement.assignments.len(), 2);
+        assert_eq!(placement.unplaceable.len(), 2);
+        assert!(placement.unplaceable.iter().all(|r| r.replica == 1));
+    }
+
+    #[test]
+    fn branch_and_bound_beats_greedy() {
+        // First fit puts 5 and 4 onto the first node and then has no room for the 2;
+        // the optimum splits the shards into 5 + 3 + 2 and 4 + 3 + 3.
+        let simulation = simulation(&[5, 4, 3, 3, 3, 2], 40, 2);
+        let greedy = simulation.place(&request(PlacementStrategy::FirstFitDecreasing, 1));
+        let exact = simulation.place(&request(PlacementStrategy::BranchAndBound, 1));
+        assert_eq!(greedy.unplaceable.len(), 1);
+        assert!(exact.unplaceable.is_empty());
+        assert!(exact
+            .utilization
+            .iter()
+            .all(|u| (u.memory - 1.).abs() < 1e-9));
+    }
+}
@@ -0,0 +1,417 @@
+use crate::filter::FilterPlan;
+use crate::simulation::Simulation;
+use crate::stats::percentile;
+use crate::timing::Seconds;
+use crate::workload::{NodeWork, TenantLoad, Workload};
+
+/// A closed-form estimate of a queue's steady state.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QueueEstimate {
+    pub utilization: f64,
+    /// The probability that an arriving request has to wait.
+    pub wait_probability: f64,
+    pub mean_wait: Seconds,
+    /// The 99th percentile of the waiting time.
+    pub p99_wait: Seconds,
+}
+
+/// The analytical estimate for one tenant of a workload.
+#[derive(Debug)]
+pub struct TenantEstimate {
+    pub index_id: usize,
+    pub mean_latency: Seconds,
+    pub p99_latency: Seconds,
+}
+
+/// The analytical estimate of a tenant next to the simulated result.
+#[derive(Debug)]
+pub struct Divergence {
+    pub index_id: usize,
+    pub analytical_mean: Seconds,
+    pub simulated_mean: Seconds,
+    pub analytical_p99: Seconds,
+    pub simulated_p99: Seconds,
+    /// `|analytical - simulated| / simulated` of the mean latency.
+    pub mean_error: f64,
+    pub p99_error: f64,
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    /// The utilization of the nodes, averaged over all nodes.
+    pub analytical_utilization: f64,
+    pub simulated_utilization: f64,
+    pub tenants: Vec<Divergence>,
+}
+
+impl Comparison {
+    /// The tenants whose mean or p99 estimate is off by more than `tolerance`.
+    pub fn diverging(&self, tolerance: f64) -> impl Iterator<Item = &Divergence> {
+        self.tenants
+            .iter()
+            .filter(move |t| t.mean_error > tolerance || t.p99_error > tolerance)
+    }
+}
+
+/// The number of selectivities the service time of a filtering tenant is averaged over.
+const SELECTIVITY_QUANTILES: usize = 100;
+
+/// The equally likely node work of a tenant's queries, along with their overheads.
+type TenantWork = Vec<(NodeWork, f64)>;
+
+/// The Erlang C formula: the probability that a request arriving at `servers` servers
+/// offered `load = arrival_rate * service_time` Erlangs has to wait.
+pub fn erlang_c(servers: usize, load: f64) -> f64 {
+    let utilization = load / servers as f64;
+    if utilization >= 1. {
+        return 1.;
+    }
+
+    // Erlang B by recursion, converted to Erlang C.
+    let mut blocking = 1.;
+    for k in 1..=servers {
+        blocking = load * blocking / (k as f64 + load * blocking);
+    }
+    blocking / (1. - utilization * (1. - blocking))
+}
+
+/// Estimates an M/M/c queue. Returns `None` if the queue is unstable.
+pub fn mmc(arrival_rate: f64, service_time: Seconds, servers: usize) -> Option<QueueEstimate> {
+    mgc(arrival_rate, service_time, 1., servers)
+}
+
+/// Estimates an M/G/c queue using the Allen-Cunneen approximation, which scales the
+/// M/M/c waiting time by `(1 + scv) / 2` for service times with the given squared
+/// coefficient of variation. Returns `None` if the queue is unstable.
+pub fn mgc(
+    arrival_rate: f64,
+    service_time: Seconds,
+    service_scv: f64,
+    servers: usize,
+) -> Option<QueueEstimate> {
+    assert_ne!(servers, 0);
+    let load = arrival_rate * *service_time;
+    let utilization = load / servers as f64;
+    if utilization >= 1. {
+        return None;
+    }
+
+    let wait_probability = erlang_c(servers, load);
+    let drain_rate = servers as f64 / *service_time - arrival_rate;
+    let variability = (1. + service_scv) / 2.;
+    let p99_wait = if wait_probability > 0.01 {
+        (wait_probability / 0.01).ln() / drain_rate * variability
+    } else {
+        0.
+    };
+    Some(QueueEstimate {
+        utilization,
+        wait_probability,
+        mean_wait: Seconds(wait_probability / drain_rate * variability),
+        p99_wait: Seconds(p99_wait),
+    })
+}
+
+/// Estimates an M/G/1 queue using the Pollaczek-Khinchine formula. Returns `None` if
+/// the queue is unstable.
+pub fn mg1(arrival_rate: f64, service_time: Seconds, service_scv: f64) -> Option<QueueEstimate> {
+    let utilization = arrival_rate * *service_time;
+    if utilization >= 1. {
+        return None;
+    }
+
+    let second_moment = (1. + service_scv) * *service_time * *service_time;
+    let mean_wait = arrival_rate * second_moment / (2. * (1. - utilization));
+    // The tail is approximated as exponential with the exact mean.
+    let conditional_mean = mean_wait / utilization.max(f64::MIN_POSITIVE);
+    let p99_wait = if utilization > 0.01 {
+        conditional_mean * (utilization / 0.01).ln()
+    } else {
+        0.
+    };
+    Some(QueueEstimate {
+        utilization,
+        wait_probability: utilization,
+        mean_wait: Seconds(mean_wait),
+        p99_wait: Seconds(p99_wait),
+    })
+}
+
+/// Approximates the mean response time of a fork-join query over `num_shards` identical
+/// M/M/1 shard queues with the given utilization, following Nelson and Tantawi.
+///
+/// The approximation requires exponential service times. Shards that serve the same
+/// queries with deterministic service times are synchronized instead, see
+/// [`Simulation::estimate_fork_join`].
+pub fn fork_join(num_shards: usize, shard_response: Seconds, utilization: f64) -> Seconds {
+    let harmonic = |n: usize| (1..=n).map(|i| 1. / i as f64).sum::<f64>();
+    if num_shards <= 1 {
+        return shard_response;
+    }
+    let ratio = harmonic(num_shards) / harmonic(2);
+    let scale = (ratio + 4. / 11. * (1. - ratio) * utilization) * (12. - utilization) / 8.;
+    Seconds(*shard_response * scale)
+}
+
+impl Simulation {
+    /// Estimates the latency of every tenant of a workload analytically.
+    ///
+    /// Matching the model of [`Simulation::simulate_workload`], every node is treated as
+    /// an M/G/1 queue serving the shard requests of all tenants with shards on it. The
+    /// service times of filtering tenants are integrated over their selectivity
+    /// distribution. Since the shard requests of a query arrive at all of its nodes at
+    /// once, a query waits about as long as the request on its slowest node. Returns one
+    /// estimate per node along with the tenant estimates, or `None` if the workload is
+    /// empty or a node cannot keep up.
+    pub fn estimate_workload(
+        &self,
+        workload: &Workload,
+    ) -> Option<(Vec<QueueEstimate>, Vec<TenantEstimate>)> {
+        if workload.tenants.is_empty() {
+            return None;
+        }
+        let work: Vec<TenantWork> = workload
+            .tenants
+            .iter()
+            .map(|t| self.tenant_work(t))
+            .collect();
+
+        // The arrival rate and the first two moments of the service time of every node.
+        let mut arrival_rate = vec![0.; self.num_nodes];
+        let mut first_moment = vec![0.; self.num_nodes];
+        let mut second_moment = vec![0.; self.num_nodes];
+        for (tenant, samples) in workload.tenants.iter().zip(&work) {
+            let weight = tenant.queries_per_second / samples.len() as f64;
+            for &(node, _) in &samples[0].0 {
+                arrival_rate[node] += tenant.queries_per_second;
+            }
+            for (node_work, _) in samples {
+                for &(node, service) in node_work {
+                    first_moment[node] += weight * service;
+                    second_moment[node] += weight * service * service;
+                }
+            }
+        }
+        let queues = (0..self.num_nodes)
+            .map(|node| {
+                let rate = arrival_rate[node];
+                if rate <= 0. {
+                    return mg1(0., Seconds(0.), 0.);
+                }
+                let mean = first_moment[node] / rate;
+                let scv = (second_moment[node] / rate / (mean * mean) - 1.).max(0.);
+                mg1(rate, Seconds(mean), scv)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let tenants = workload
+            .tenants
+            .iter()
+            .zip(work)
+            .map(|(tenant, samples)| {
+                let response = |node_work: &NodeWork, wait: fn(&QueueEstimate) -> Seconds| {
+                    node_work
+                        .iter()
+                        .map(|&(node, service)| *wait(&queues[node]) + service)
+                        .fold(0., f64::max)
+                };
+                let n = samples.len() as f64;
+                let mean = samples
+                    .iter()
+                    .map(|(node_work, overhead)| response(node_work, |q| q.mean_wait) + overhead)
+                    .sum::<f64>()
+                    / n;
+                let mut tails: Vec<f64> = samples
+                    .iter()
+                    .map(|(node_work, overhead)| response(node_work, |q| q.p99_wait) + overhead)
+                    .collect();
+                tails.sort_by(f64::total_cmp);
+                TenantEstimate {
+                    index_id: tenant.index_id,
+                    mean_latency: Seconds(mean),
+                    p99_latency: Seconds(percentile(&tails, 0.99)),
+                }
+            })
+            .collect();
+        Some((queues, tenants))
+    }
+
+    /// The work a tenant's queries put on the nodes.
+    fn tenant_work(&self, tenant: &TenantLoad) -> TenantWork {
+        let Some(selectivity) = tenant.selectivity else {
+            return vec![self.node_work(tenant.index_id, &FilterPlan::unfiltered())];
+        };
+
+        // Midpoint quadrature over the quantiles of the selectivity distribution.
+        let strategy = self.index(tenant.index_id).filter_strategy;
+        (0..SELECTIVITY_QUANTILES)
+            .map(|i| {
+                let u = (i as f64 + 0.5) / SELECTIVITY_QUANTILES as f64;
+                let plan = strategy.plan(selectivity.quantile(u));
+                self.node_work(tenant.index_id, &plan)
+            })
+            .collect()
+    }
+
+    /// Estimates the mean latency of an index whose nodes each queue the shard requests
+    /// of queries arriving at the given rate. Returns `None` if the busiest node cannot
+    /// keep up.
+    ///
+    /// Every node sees the same arrivals and serves them in a deterministic time, so the
+    /// node queues move in lockstep: the node with the longest service time also waits
+    /// the longest, and the query finishes when that node does. The estimate therefore
+    /// does not grow with the fan-out the way [`fork_join`] does.
+    pub fn estimate_fork_join(&self, index_id: usize, queries_per_second: f64) -> Option<Seconds> {
+        let (work, overhead) = self.node_work(index_id, &FilterPlan::unfiltered());
+        let mut response = 0f64;
+        for (_, service) in work {
+            let queue = mg1(queries_per_second, Seconds(service), 0.)?;
+            response = response.max(*queue.mean_wait + service);
+        }
+        Some(Seconds(response + overhead))
+    }
+
+    /// Runs the analytical estimator and the discrete simulation of a workload and
+    /// reports how far they diverge. Returns `None` if the estimator deems the workload
+    /// unstable.
+    pub fn compare_with_simulation(&self, workload: &Workload) -> Option<Comparison> {
+        let (queues, estimates) = self.estimate_workload(workload)?;
+        let simulated = self.simulate_workload(workload);
+        let relative = |a: Seconds, b: Seconds| (*a - *b).abs() / b.0.max(f64::MIN_POSITIVE);
+
+        let tenants = estimates
+            .into_iter()
+            .zip(&simulated.tenants)
+            .map(|(estimate, tenant)| Divergence {
+                index_id: estimate.index_id,
+                mean_error: relative(estimate.mean_latency, tenant.latency.mean),
+                p99_error: relative(estimate.p99_latency, tenant.latency.p99),
+                analytical_mean: estimate.mean_latency,
+                simulated_mean: tenant.latency.mean,
+                analytical_p99: estimate.p99_latency,
+                simulated_p99: tenant.latency.p99,
+            })
+            .collect();
+        Some(Comparison {
+            analytical_utilization: queues.iter().map(|q| q.utilization).sum::<f64>()
+                / queues.len() as f64,
+            simulated_utilization: simulated.utilization,
+            tenants,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{FilterStrategy, Selectivity};
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::Nanoseconds;
+
+    #[test]
+    fn closed_forms_agree_on_single_server() {
+        // With one server, Erlang C reduces to the utilization.
+        assert!((erlang_c(1, 0.6) - 0.6).abs() < 1e-12);
+        // M/M/1: W = rho / (mu - lambda).
+        let mm1 = mmc(6., Seconds(0.1), 1).unwrap();
+        assert!((*mm1.mean_wait - 0.6 / 4.).abs() < 1e-12);
+        let pk = mg1(6., Seconds(0.1), 1.).unwrap();
+        assert!((*pk.mean_wait - *mm1.mean_wait).abs() < 1e-12);
+        // Deterministic service halves the wait.
+        let md1 = mg1(6., Seconds(0.1), 0.).unwrap();
+        assert!((*md1.mean_wait * 2. - *mm1.mean_wait).abs() < 1e-12);
+        assert!(mmc(10., Seconds(0.1), 1).is_none());
+    }
+
+    #[test]
+    fn estimator_integrates_filtered_service_times() {
+        let index = Index::new_from_shards(0, &[1_000_000], 50).with_filter_strategy(
+            FilterStrategy::PreFilter {
+                predicate_cost: Nanoseconds(1.).into(),
+            },
+        );
+        let simulation = SimulationBuilder::default()
+            .with_index(index)
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_nodes(2)
+            .build();
+        let selectivity = Selectivity::log_uniform(0.01, 1.);
+        let workload = Workload::new(Seconds(200.), 3).with_filtered_tenant(0, 100., selectivity);
+
+        let (_, estimates) = simulation.estimate_workload(&workload).unwrap();
+        assert!(estimates[0].mean_latency < simulation.simulate_find(0).duration);
+        let comparison = simulation.compare_with_simulation(&workload).unwrap();
+        assert!(
+            (comparison.analytical_utilization - comparison.simulated_utilization).abs() < 0.05
+        );
+        assert!(comparison.tenants[0].mean_error < 0.1);
+    }
+
+    #[test]
+    fn fork_join_grows_with_fan_out() {
+        let one = fork_join(1, Seconds(1.), 0.5);
+        let two = fork_join(2, Seconds(1.), 0.5);
+        let many = fork_join(32, Seconds(1.), 0.5);
+        assert_eq!(one, Seconds(1.));
+        // For two shards the approximation is exact: (12 - rho) / 8.
+        assert!((*two - 11.5 / 8.).abs() < 1e-12);
+        assert!(many > two);
+    }
+
+    #[test]
+    fn estimator_tracks_simulation() {
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000], 50))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_nodes(2)
+            .build();
+        let light = Workload::new(Seconds(200.), 3).with_tenant(0, 50.);
+        let comparison = simulation.compare_with_simulation(&light).unwrap();
+        assert!(
+            (comparison.analytical_utilization - comparison.simulated_utilization).abs() < 0.05
+        );
+        assert_eq!(comparison.diverging(0.1).count(), 0);
+
+        let overloaded = Workload::new(Seconds(10.), 3).with_tenant(0, 300.);
+        assert!(simulation.compare_with_simulation(&overloaded).is_none());
+
+        assert!(simulation
+            .estimate_workload(&Workload::new(Seconds(10.), 3))
+            .is_none());
+
+        let fork_join = simulation.estimate_fork_join(0, 50.).unwrap();
+        assert!(fork_join > simulation.simulate_find(0).duration);
+    }
+
+    #[test]
+    fn fork_join_estimate_tracks_simulation_at_large_fan_out() {
+        let build = |num_shards: usize, num_nodes: usize| {
+            SimulationBuilder::default()
+                .with_index(Index::new_evenly_split(0, 1_600_000, num_shards, 50))
+                .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+                .with_nodes(num_nodes)
+                .build()
+        };
+        let workload = Workload::new(Seconds(200.), 3).with_tenant(0, 150.);
+
+        for (num_shards, num_nodes) in [(4, 4), (16, 4), (16, 16)] {
+            let simulation = build(num_shards, num_nodes);
+            let estimate = simulation.estimate_fork_join(0, 150.).unwrap();
+            let simulated = simulation.simulate_workload(&workload).tenants[0]
+                .latency
+                .mean;
+            assert!((*estimate - *simulated).abs() / *simulated < 0.1);
+        }
+
+        // Identical synchronized shards respond like a single one of them.
+        let wide = build(16, 16).estimate_fork_join(0, 150.).unwrap();
+        let single = SimulationBuilder::default()
+            .with_index(Index::new(0, 100_000, 50))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .build()
+            .estimate_fork_join(0, 150.)
+            .unwrap();
+        assert!((*wide - *single).abs() < 1e-12);
+    }
+}
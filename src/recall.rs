@@ -0,0 +1,288 @@
+use crate::filter::FilterPlan;
+use crate::index::Index;
+use crate::routing::squared_distance;
+use crate::simulation::{Simulation, SimulationResult};
+use crate::timing::Seconds;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// The parameters of a sharded top-k query that affect its recall.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RecallConfig {
+    /// The number of results the query asks for.
+    pub k: usize,
+    /// The number of results every shard returns to the coordinator.
+    pub per_shard_k: usize,
+    /// The fraction of its true top `per_shard_k` a shard's approximate index finds.
+    pub ann_recall: f64,
+    /// The time the coordinator spends merging a single returned candidate.
+    pub merge_cost_per_candidate: Seconds,
+}
+
+impl RecallConfig {
+    pub fn new(k: usize, per_shard_k: usize, ann_recall: f64) -> Self {
+        assert_ne!(k, 0);
+        assert_ne!(per_shard_k, 0);
+        assert!((0. ..=1.).contains(&ann_recall));
+        Self {
+            k,
+            per_shard_k,
+            ann_recall,
+            merge_cost_per_candidate: Seconds(0.),
+        }
+    }
+
+    pub fn with_merge_cost<C>(mut self, cost: C) -> Self
+    where
+        C: Into<Seconds>,
+    {
+        self.merge_cost_per_candidate = cost.into();
+        self
+    }
+}
+
+/// Estimates the recall of a top-k query over shards of the given sizes.
+///
+/// The true top `k` are assumed to be spread across shards proportionally to their size,
+/// so each shard holds a binomially distributed number of them, of which it returns at
+/// most `per_shard_k`.
+pub fn estimate_recall(shard_sizes: &[usize], config: &RecallConfig) -> f64 {
+    estimate_routed_recall(shard_sizes, 1., config)
+}
+
+/// Estimates the recall of a top-k query that only probes shards of the given sizes,
+/// which together hold `routing_recall` of the true top `k`, e.g. as estimated by
+/// [`crate::routing::Routing::recall`]. Those neighbours are spread across the probed
+/// shards proportionally to their size.
+pub fn estimate_routed_recall(
+    probed_sizes: &[usize],
+    routing_recall: f64,
+    config: &RecallConfig,
+) -> f64 {
+    assert!((0. ..=1.).contains(&routing_recall));
+    let total: usize = probed_sizes.iter().sum();
+    if total == 0 {
+        return routing_recall;
+    }
+
+    let k = config.k.min(total);
+    let found: f64 = probed_sizes
+        .iter()
+        .map(|&size| {
+            let p = routing_recall * size as f64 / total as f64;
+            expected_truncated_binomial(k, p, config.per_shard_k)
+        })
+        .sum();
+    found / k as f64 * config.ann_recall
+}
+
+/// `E[min(X, limit)]` for `X ~ Binomial(n, p)`.
+fn expected_truncated_binomial(n: usize, p: f64, limit: usize) -> f64 {
+    if p <= 0. {
+        return 0.;
+    }
+    if p >= 1. {
+        return n.min(limit) as f64;
+    }
+
+    let mut probability = (1. - p).powi(n as i32);
+    let mut expected = 0.;
+    for x in 0..=n {
+        expected += x.min(limit) as f64 * probability;
+        probability *= (n - x) as f64 / (x + 1) as f64 * p / (1. - p);
+    }
+    expected
+}
+
+/// Measures the recall of a top-k query on generated data. Random vectors are assigned
+/// to shards in proportion to their sizes; every shard finds each of its true top
+/// `per_shard_k` with probability `ann_recall`.
+pub fn measure_recall(
+    shard_sizes: &[usize],
+    config: &RecallConfig,
+    dimensions: usize,
+    num_queries: usize,
+    seed: u64,
+) -> f64 {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let random_vector =
+        |rng: &mut StdRng| -> Vec<f32> { (0..dimensions).map(|_| rng.gen::<f32>()).collect() };
+    let shards: Vec<Vec<Vec<f32>>> = shard_sizes
+        .iter()
+        .map(|&size| (0..size).map(|_| random_vector(&mut rng)).collect())
+        .collect();
+
+    let mut recall = 0.;
+    for _ in 0..num_queries {
+        let query = random_vector(&mut rng);
+        let mut all = Vec::new();
+        let mut returned = Vec::new();
+        for (shard, vectors) in shards.iter().enumerate() {
+            let mut distances: Vec<(f64, usize, usize)> = vectors
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (squared_distance(v, &query), shard, i))
+                .collect();
+            distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+            returned.extend(
+                distances
+                    .iter()
+                    .take(config.per_shard_k)
+                    .filter(|_| rng.gen::<f64>() < config.ann_recall)
+                    .cloned(),
+            );
+            all.extend(distances);
+        }
+
+        all.sort_by(|a, b| a.0.total_cmp(&b.0));
+        returned.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let k = config.k.min(all.len());
+        let truth: Vec<_> = all[..k].iter().map(|&(_, s, i)| (s, i)).collect();
+        let found = returned
+            .iter()
+            .take(k)
+            .filter(|&&(_, s, i)| truth.contains(&(s, i)))
+            .count();
+        recall += found as f64 / k.max(1) as f64;
+    }
+    recall / num_queries.max(1) as f64
+}
+
+impl Simulation {
+    /// Like [`Simulation::simulate_find`], with the recall lowered by the per-shard top-k
+    /// truncation and the approximate search of every shard, and the duration including
+    /// the merge of all returned candidates.
+    pub fn simulate_find_with_recall(
+        &self,
+        index_id: usize,
+        config: &RecallConfig,
+    ) -> SimulationResult {
+        self.find_with_recall(self.index(index_id), config)
+    }
+
+    fn find_with_recall(&self, index: &Index, config: &RecallConfig) -> SimulationResult {
+        let shard_ids = self.routing.select_shards(index, None);
+        let result = self.simulate_shards(index, &shard_ids, &FilterPlan::unfiltered());
+        // Only the probed shards contribute results.
+        let probed_sizes: Vec<usize> = shard_ids
+            .iter()
+            .map(|&id| index.shard(id).unwrap().num_vectors)
+            .collect();
+        let merge = config.merge_cost_per_candidate * (shard_ids.len() * config.per_shard_k);
+        SimulationResult {
+            duration: result.duration + merge,
+            duration_total: result.duration_total + merge,
+            recall: estimate_routed_recall(&probed_sizes, result.recall, config),
+            ..result
+        }
+    }
+
+    /// Re-shards the index into `1..=max_shards` evenly sized shards, tries every
+    /// per-shard k up to `config.k`, and returns the shard count and per-shard k with
+    /// the lowest duration whose recall reaches `min_recall`.
+    pub fn optimal_shard_count_with_recall(
+        &self,
+        index_id: usize,
+        max_shards: usize,
+        config: &RecallConfig,
+        min_recall: f64,
+    ) -> Option<(usize, usize, SimulationResult)> {
+        let index = self.index(index_id);
+        let mut best: Option<(usize, usize, SimulationResult)> = None;
+        for num_shards in 1..=max_shards {
+            let candidate = Index::new_evenly_split(
+                index.index_id,
+                index.num_vectors,
+                num_shards,
+                index.vector_length,
+            );
+            for per_shard_k in 1..=config.k {
+                let config = RecallConfig {
+                    per_shard_k,
+                    ..*config
+                };
+                let result = self.find_with_recall(&candidate, &config);
+                if result.recall >= min_recall
+                    && best
+                        .as_ref()
+                        .is_none_or(|(_, _, b)| result.duration < b.duration)
+                {
+                    best = Some((num_shards, per_shard_k, result));
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::RecallModel;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Microseconds, Nanoseconds};
+
+    #[test]
+    fn analytic_estimate_matches_measurement() {
+        let config = RecallConfig::new(10, 4, 0.95);
+        let sizes = [500; 4];
+        let estimate = estimate_recall(&sizes, &config);
+        let measured = measure_recall(&sizes, &config, 8, 200, 5);
+        assert!(estimate < 0.95);
+        assert!((estimate - measured).abs() < 0.05);
+
+        // With a full per-shard k only the approximate search loses results.
+        let config = RecallConfig::new(10, 10, 0.95);
+        assert!((estimate_recall(&sizes, &config) - 0.95).abs() < 1e-12);
+    }
+
+    #[test]
+    fn recall_grows_with_fan_out_and_per_shard_k() {
+        // Two shards returning three results each can at best find six of ten.
+        let config = RecallConfig::new(10, 3, 1.);
+        let few = estimate_recall(&[1000; 2], &config);
+        let many = estimate_recall(&[1000; 8], &config);
+        assert!(few < 0.6);
+        assert!(many > few);
+        assert!(estimate_recall(&[1000; 2], &RecallConfig::new(10, 6, 1.)) > few);
+    }
+
+    #[test]
+    fn truncation_applies_to_probed_shards() {
+        let index = Index::new_from_shards(0, &[1000; 8], 2);
+        for (i, shard_id) in index.shard_ids().into_iter().enumerate() {
+            index.set_centroid(shard_id, vec![i as f32, 0.]).unwrap();
+        }
+        let simulation = SimulationBuilder::default()
+            .with_index(index)
+            .with_shard_pruning(2, RecallModel::Clustered { locality: 20. })
+            .build();
+        let config = RecallConfig::new(10, 3, 1.);
+
+        // Nearly all neighbours sit in the two probed shards, which return three each.
+        let result = simulation.simulate_find_with_recall(0, &config);
+        let routing_recall = simulation.simulate_find(0).recall;
+        assert!(routing_recall > 0.99);
+        assert!(result.recall < 0.6);
+        assert!(result.recall < routing_recall * estimate_recall(&[1000; 8], &config));
+    }
+
+    #[test]
+    fn optimizer_respects_recall_floor() {
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000], 128))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_scatter_gather_cost(Microseconds(100.), Microseconds(100.))
+            .build();
+        let config = RecallConfig::new(10, 10, 0.98).with_merge_cost(Microseconds(10.));
+
+        let (shards, per_shard_k, result) = simulation
+            .optimal_shard_count_with_recall(0, 16, &config, 0.9)
+            .unwrap();
+        assert!(result.recall >= 0.9);
+        assert!(shards > 1 && per_shard_k < 10);
+        assert!(simulation
+            .optimal_shard_count_with_recall(0, 16, &config, 0.99)
+            .is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::index::{Index, ShardId};
+
+/// Determines which shards of an index a query fans out to.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Routing {
+    /// Scatter the query to every shard of the index.
+    #[default]
+    FullScatter,
+    /// Only visit the `nprobe_shards` shards whose centroids are closest to the query.
+    Partitioned {
+        nprobe_shards: usize,
+        recall: RecallModel,
+    },
+}
+
+/// Estimates the fraction of true nearest neighbours found when only part of the
+/// index is visited.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RecallModel {
+    /// Neighbours are spread uniformly across shards, so recall equals the fraction
+    /// of vectors visited.
+    Uniform,
+    /// Neighbours cluster around the query's nearest centroids. A `locality` of `1.0`
+    /// degrades to [`RecallModel::Uniform`]; higher values concentrate neighbours in
+    /// the first probed shards.
+    Clustered { locality: f64 },
+}
+
+impl RecallModel {
+    pub fn recall(&self, visited_fraction: f64) -> f64 {
+        let visited_fraction = visited_fraction.clamp(0., 1.);
+        match *self {
+            RecallModel::Uniform => visited_fraction,
+            RecallModel::Clustered { locality } => {
+                1. - (1. - visited_fraction).powf(locality.max(1.))
+            }
+        }
+    }
+}
+
+impl Routing {
+    /// Selects the shards a query is sent to, in ascending shard ID order.
+    ///
+    /// Without a query vector the heaviest shards are probed, which gives a pessimistic
+    /// estimate of the fan-out cost.
+    pub fn select_shards(&self, index: &Index, query: Option<&[f32]>) -> Vec<ShardId> {
+        if let Some(query) = query {
+            assert_eq!(
+                query.len(),
+                index.vector_length,
+                "Query has the wrong dimension"
+            );
+        }
+        let nprobe_shards = match *self {
+            Routing::FullScatter => return index.shard_ids(),
+            Routing::Partitioned { nprobe_shards, .. } => nprobe_shards,
+        };
+
+        let mut selected = Vec::new();
+        let mut candidates = Vec::new();
+        for shard in index {
+            match (&shard.centroid, query) {
+                (None, _) => selected.push(shard.shard_id),
+                (Some(centroid), Some(query)) => {
+                    candidates.push((squared_distance(centroid, query), shard.shard_id))
+                }
+                (Some(_), None) => candidates.push((-(shard.weight() as f64), shard.shard_id)),
+            }
+        }
+
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        selected.extend(candidates.into_iter().take(nprobe_shards).map(|(_, id)| id));
+        selected.sort();
+        selected
+    }
+
+    /// Estimates the recall of a query that visits `visited_vectors` of the index.
+    pub fn recall(&self, index: &Index, visited_vectors: usize) -> f64 {
+        match self {
+            Routing::FullScatter => 1.,
+            Routing::Partitioned { recall, .. } => {
+                if index.is_empty() {
+                    return 1.;
+                }
+                recall.recall(visited_vectors as f64 / index.len() as f64)
+            }
+        }
+    }
+}
+
+pub fn squared_distance(a: &[f32], b: &[f32]) -> f64 {
+    assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_scatter_visits_all_shards() {
+        let index = Index::new_from_shards(0, &[10, 20, 30], 2);
+        let shards = Routing::FullScatter.select_shards(&index, None);
+        assert_eq!(shards, index.shard_ids());
+    }
+
+    #[test]
+    fn partitioned_visits_nearest_shards() {
+        let index = Index::new_from_shards(0, &[10, 20, 30], 2);
+        let ids = index.shard_ids();
+        index.set_centroid(ids[0], vec![0., 0.]).unwrap();
+        index.set_centroid(ids[1], vec![10., 10.]).unwrap();
+        index.set_centroid(ids[2], vec![1., 1.]).unwrap();
+
+        let routing = Routing::Partitioned {
+            nprobe_shards: 2,
+            recall: RecallModel::Uniform,
+        };
+        let shards = routing.select_shards(&index, Some(&[0.5, 0.5]));
+        assert_eq!(shards, vec![ids[0], ids[2]]);
+        assert_eq!(routing.recall(&index, 40), 40. / 60.);
+    }
+
+    #[test]
+    #[should_panic(expected = "Query has the wrong dimension")]
+    fn query_dimension_is_checked() {
+        let index = Index::new_from_shards(0, &[10, 20], 2);
+        Routing::FullScatter.select_shards(&index, Some(&[0.5]));
+    }
+
+    #[test]
+    fn clustered_recall_exceeds_uniform() {
+        let uniform = RecallModel::Uniform.recall(0.25);
+        let clustered = RecallModel::Clustered { locality: 4. }.recall(0.25);
+        assert!(clustered > uniform);
+        assert_eq!(RecallModel::Clustered { locality: 4. }.recall(1.), 1.);
+    }
+}
@@ -0,0 +1,487 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// The degree, polynomial coefficients and initial direction numbers of the Sobol
+/// sequence dimensions after the first, from Joe and Kuo's `new-joe-kuo-6.21201`.
+const SOBOL_DIRECTIONS: [(u32, u32, &[u32]); 19] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+];
+
+/// The largest number of dimensions [`sobol`] supports.
+pub const MAX_SOBOL_DIMENSIONS: usize = SOBOL_DIRECTIONS.len() + 1;
+
+const SOBOL_BITS: usize = 32;
+
+/// The bases of the Halton sequence dimensions.
+const HALTON_BASES: [u64; 20] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71,
+];
+
+/// The largest number of dimensions [`halton`] supports.
+pub const MAX_HALTON_DIMENSIONS: usize = HALTON_BASES.len();
+
+/// How the values of a sweep axis are chosen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sampler {
+    /// Evenly spaced values including both bounds. The grid axes of a sweep are fully
+    /// crossed with each other and with the points drawn on the remaining axes.
+    Grid { levels: usize },
+    /// Independent uniform draws.
+    Uniform,
+    /// One value in each of the equally sized strata, see [`latin_hypercube`].
+    LatinHypercube,
+    /// One dimension of a Sobol sequence shared by all Sobol axes, see [`sobol`].
+    Sobol,
+    /// One dimension of a Halton sequence shared by all Halton axes, see [`halton`].
+    Halton,
+}
+
+/// How a value in the unit interval maps onto the range of an axis.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scale {
+    Linear,
+    /// Spreads values evenly over orders of magnitude. Requires a positive range.
+    Log,
+}
+
+/// A parameter varied by a [`Sweep`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    pub scale: Scale,
+    pub sampler: Sampler,
+}
+
+/// A design of experiments over several axes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub axes: Vec<Axis>,
+    /// The number of points drawn per combination of grid values. Every combination
+    /// reuses the same points on the non-grid axes.
+    pub samples: usize,
+    pub seed: u64,
+}
+
+/// How evenly a set of points covers the unit hypercube.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    /// For every axis, the fraction of `min(n, 100)` equally sized strata holding a point.
+    pub axis_coverage: Vec<f64>,
+    /// The fraction of cells holding a point in the two-dimensional projections onto
+    /// every pair of axes, each split into `floor(sqrt(n))` strata per axis, averaged
+    /// over all pairs.
+    pub pair_coverage: f64,
+    /// The smallest distance between two points, relative to the diagonal of the
+    /// hypercube.
+    pub min_distance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepPoints {
+    /// One value per axis for every point, in the order of the axes.
+    pub values: Vec<Vec<f64>>,
+    /// The coverage of the points before they are mapped onto the axis ranges.
+    pub coverage: Coverage,
+}
+
+impl Axis {
+    pub fn new(name: &str, min: f64, max: f64, sampler: Sampler) -> Self {
+        assert!(min <= max);
+        if let Sampler::Grid { levels } = sampler {
+            assert_ne!(levels, 0);
+        }
+        Self {
+            name: name.to_string(),
+            min,
+            max,
+            scale: Scale::Linear,
+            sampler,
+        }
+    }
+
+    pub fn log(mut self) -> Self {
+        assert!(self.min > 0.);
+        self.scale = Scale::Log;
+        self
+    }
+
+    /// Maps a value in the unit interval onto the range of the axis.
+    pub fn value(&self, unit: f64) -> f64 {
+        match self.scale {
+            Scale::Linear => self.min + (self.max - self.min) * unit,
+            Scale::Log => self.min * (self.max / self.min).powf(unit),
+        }
+    }
+}
+
+impl Sweep {
+    pub fn new(samples: usize, seed: u64) -> Self {
+        assert_ne!(samples, 0);
+        Self {
+            axes: Vec::new(),
+            samples,
+            seed,
+        }
+    }
+
+    pub fn with_axis(mut self, axis: Axis) -> Self {
+        self.axes.push(axis);
+        self
+    }
+
+    /// The number of points of the sweep: `samples` for every combination of grid values.
+    pub fn len(&self) -> usize {
+        self.grid_levels().product::<usize>() * self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn grid_levels(&self) -> impl Iterator<Item = usize> + '_ {
+        self.axes.iter().filter_map(|axis| match axis.sampler {
+            Sampler::Grid { levels } => Some(levels),
+            _ => None,
+        })
+    }
+
+    /// The points of the sweep in the unit hypercube, one coordinate per axis.
+    ///
+    /// The non-grid axes draw `samples` points once, and every combination of grid values
+    /// reuses them, so each grid cell is covered by the whole space-filling design.
+    pub fn unit_points(&self) -> Vec<Vec<f64>> {
+        let n = self.len();
+        let count = |sampler: Sampler| self.axes.iter().filter(|a| a.sampler == sampler).count();
+        let sobol_points = sobol(self.samples, count(Sampler::Sobol));
+        let halton_points = halton(self.samples, count(Sampler::Halton));
+        let lhs_points = latin_hypercube(self.samples, count(Sampler::LatinHypercube), self.seed);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let mut points = vec![Vec::with_capacity(self.axes.len()); n];
+        let mut grid_stride = self.samples;
+        let (mut sobol_axis, mut halton_axis, mut lhs_axis) = (0, 0, 0);
+        for axis in &self.axes {
+            let uniform: Vec<f64> = match axis.sampler {
+                Sampler::Uniform => (0..self.samples).map(|_| rng.gen::<f64>()).collect(),
+                _ => Vec::new(),
+            };
+            for (i, point) in points.iter_mut().enumerate() {
+                let sample = i % self.samples;
+                let unit = match axis.sampler {
+                    Sampler::Grid { levels: 1 } => 0.5,
+                    Sampler::Grid { levels } => {
+                        ((i / grid_stride) % levels) as f64 / (levels - 1) as f64
+                    }
+                    Sampler::Uniform => uniform[sample],
+                    Sampler::LatinHypercube => lhs_points[sample][lhs_axis],
+                    Sampler::Sobol => sobol_points[sample][sobol_axis],
+                    Sampler::Halton => halton_points[sample][halton_axis],
+                };
+                point.push(unit);
+            }
+            match axis.sampler {
+                Sampler::Grid { levels } => grid_stride *= levels,
+                Sampler::Uniform => {}
+                Sampler::LatinHypercube => lhs_axis += 1,
+                Sampler::Sobol => sobol_axis += 1,
+                Sampler::Halton => halton_axis += 1,
+            }
+        }
+        points
+    }
+
+    /// The points of the sweep mapped onto the axis ranges, along with their coverage.
+    pub fn points(&self) -> SweepPoints {
+        let unit = self.unit_points();
+        let coverage = coverage(&unit);
+        let values = unit
+            .iter()
+            .map(|point| {
+                point
+                    .iter()
+                    .zip(&self.axes)
+                    .map(|(&u, axis)| axis.value(u))
+                    .collect()
+            })
+            .collect();
+        SweepPoints { values, coverage }
+    }
+}
+
+/// Measures how evenly points cover the unit hypercube.
+pub fn coverage(points: &[Vec<f64>]) -> Coverage {
+    let n = points.len();
+    let dimensions = points.first().map_or(0, |p| p.len());
+    let stratum = |u: f64, strata: usize| ((u * strata as f64) as usize).min(strata - 1);
+
+    let strata = n.clamp(1, 100);
+    let axis_coverage = (0..dimensions)
+        .map(|d| {
+            let mut hit = vec![false; strata];
+            for point in points {
+                hit[stratum(point[d], strata)] = true;
+            }
+            hit.iter().filter(|&&h| h).count() as f64 / strata as f64
+        })
+        .collect();
+
+    let strata = ((n as f64).sqrt() as usize).max(1);
+    let mut pairs = 0;
+    let mut pair_coverage = 0.;
+    for a in 0..dimensions {
+        for b in a + 1..dimensions {
+            let mut hit = vec![false; strata * strata];
+            for point in points {
+                hit[stratum(point[a], strata) * strata + stratum(point[b], strata)] = true;
+            }
+            pair_coverage += hit.iter().filter(|&&h| h).count() as f64 / hit.len() as f64;
+            pairs += 1;
+        }
+    }
+
+    let mut min_distance = f64::INFINITY;
+    for (i, a) in points.iter().enumerate() {
+        for b in &points[i + 1..] {
+            let distance: f64 = a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum();
+            min_distance = min_distance.min(distance);
+        }
+    }
+
+    Coverage {
+        axis_coverage,
+        pair_coverage: if pairs > 0 {
+            pair_coverage / pairs as f64
+        } else {
+            1.
+        },
+        min_distance: (min_distance / dimensions.max(1) as f64).sqrt(),
+    }
+}
+
+/// Draws `n` points from the unit hypercube of the given dimension such that every
+/// dimension has exactly one point in each of its `n` equally sized strata.
+pub fn latin_hypercube(n: usize, dimensions: usize, seed: u64) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points = vec![vec![0.; dimensions]; n];
+    let mut strata: Vec<usize> = (0..n).collect();
+    for dimension in 0..dimensions {
+        strata.shuffle(&mut rng);
+        for (point, &stratum) in points.iter_mut().zip(&strata) {
+            point[dimension] = (stratum as f64 + rng.gen::<f64>()) / n as f64;
+        }
+    }
+    points
+}
+
+/// Returns the first `n` points of the Sobol sequence in the unit hypercube of the given
+/// dimension, starting at the origin. Any power of two prefix has exactly one point in
+/// each of the equally sized strata of every dimension.
+pub fn sobol(n: usize, dimensions: usize) -> Vec<Vec<f64>> {
+    assert!(dimensions <= MAX_SOBOL_DIMENSIONS);
+    assert!(n as u64 <= 1 << SOBOL_BITS);
+    let directions: Vec<[u32; SOBOL_BITS]> = (0..dimensions).map(sobol_directions).collect();
+
+    let mut state = vec![0u32; dimensions];
+    let mut points = Vec::with_capacity(n);
+    for i in 0..n {
+        if i > 0 {
+            // Gray code order: flip the direction of the lowest zero bit of `i - 1`.
+            let bit = (!(i - 1)).trailing_zeros() as usize;
+            for (x, v) in state.iter_mut().zip(&directions) {
+                *x ^= v[bit];
+            }
+        }
+        points.push(
+            state
+                .iter()
+                .map(|&x| x as f64 / (1u64 << SOBOL_BITS) as f64)
+                .collect(),
+        );
+    }
+    points
+}
+
+/// Returns the points `1..=n` of the Halton sequence in the unit hypercube of the given
+/// dimension, whose `i`-th coordinates are the radical inverses in the `i`-th prime base.
+pub fn halton(n: usize, dimensions: usize) -> Vec<Vec<f64>> {
+    assert!(dimensions <= MAX_HALTON_DIMENSIONS);
+    (1..=n as u64)
+        .map(|i| {
+            HALTON_BASES[..dimensions]
+                .iter()
+                .map(|&base| {
+                    let (mut i, mut digits, mut denominator) = (i, 0, 1);
+                    while i > 0 {
+                        digits = digits * base + i % base;
+                        denominator *= base;
+                        i /= base;
+                    }
+                    digits as f64 / denominator as f64
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn sobol_directions(dimension: usize) -> [u32; SOBOL_BITS] {
+    let mut v = [0u32; SOBOL_BITS];
+    if dimension == 0 {
+        for (i, v) in v.iter_mut().enumerate() {
+            *v = 1 << (SOBOL_BITS - 1 - i);
+        }
+        return v;
+    }
+
+    let (degree, coefficients, initial) = SOBOL_DIRECTIONS[dimension - 1];
+    let degree = degree as usize;
+    for i in 0..SOBOL_BITS {
+        v[i] = if i < degree {
+            initial[i] << (SOBOL_BITS - 1 - i)
+        } else {
+            let mut direction = v[i - degree] ^ (v[i - degree] >> degree);
+            for k in 1..degree {
+                if (coefficients >> (degree - 1 - k)) & 1 == 1 {
+                    direction ^= v[i - k];
+                }
+            }
+            direction
+        };
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether every dimension has exactly one point in each of `points.len()` strata.
+    fn is_stratified(points: &[Vec<f64>]) -> bool {
+        let n = points.len();
+        (0..points[0].len()).all(|dimension| {
+            let mut hits = vec![0; n];
+            for point in points {
+                hits[(point[dimension] * n as f64) as usize] += 1;
+            }
+            hits.iter().all(|&h| h == 1)
+        })
+    }
+
+    #[test]
+    fn latin_hypercube_is_stratified_and_deterministic() {
+        let points = latin_hypercube(50, 4, 7);
+        assert!(is_stratified(&points));
+        assert_eq!(points, latin_hypercube(50, 4, 7));
+    }
+
+    #[test]
+    fn sobol_prefixes_are_stratified() {
+        let points = sobol(256, MAX_SOBOL_DIMENSIONS);
+        assert_eq!(points[0], vec![0.; MAX_SOBOL_DIMENSIONS]);
+        assert_eq!(points[1], vec![0.5; MAX_SOBOL_DIMENSIONS]);
+        for n in [2, 4, 16, 256] {
+            assert!(is_stratified(&points[..n]));
+        }
+    }
+
+    #[test]
+    fn halton_uses_prime_bases() {
+        let points = halton(9, 2);
+        assert_eq!(points[0], vec![0.5, 1. / 3.]);
+        assert_eq!(points[1], vec![0.25, 2. / 3.]);
+        assert!(is_stratified(
+            &points[..8].iter().map(|p| vec![p[0]]).collect::<Vec<_>>()
+        ));
+        assert!(is_stratified(
+            &points.iter().map(|p| vec![p[1]]).collect::<Vec<_>>()
+        ));
+    }
+
+    #[test]
+    fn sweep_crosses_grid_axes() {
+        let sweep = Sweep::new(8, 1)
+            .with_axis(Axis::new("threads", 1., 4., Sampler::Grid { levels: 4 }))
+            .with_axis(Axis::new("shards", 1., 2., Sampler::Grid { levels: 2 }))
+            .with_axis(Axis::new("elements", 10., 1e5, Sampler::LatinHypercube).log())
+            .with_axis(Axis::new("cost", 0., 1., Sampler::Sobol));
+        assert_eq!(sweep.len(), 64);
+
+        let points = sweep.points();
+        assert_eq!(points.values.len(), 64);
+        let combinations: std::collections::HashSet<(u64, u64)> = points
+            .values
+            .iter()
+            .map(|p| (p[0] as u64, p[1] as u64))
+            .collect();
+        assert_eq!(combinations.len(), 8);
+        assert!(points.values.iter().all(|p| (10. ..=1e5).contains(&p[2])));
+        // Log scaling puts as many values below 1,000 as above.
+        let small = points.values.iter().filter(|p| p[2] < 1e3).count();
+        assert_eq!(small, 32);
+        assert_eq!(points.coverage.axis_coverage[..2], [0.0625, 0.03125]);
+        assert_eq!(points.coverage.axis_coverage[2..], [0.125, 0.125]);
+    }
+
+    #[test]
+    fn grid_cells_reuse_the_space_filling_points() {
+        let sweep = Sweep::new(16, 1)
+            .with_axis(Axis::new("halton", 0., 1., Sampler::Halton))
+            .with_axis(Axis::new("shards", 0., 1., Sampler::Grid { levels: 2 }))
+            .with_axis(Axis::new("sobol", 0., 1., Sampler::Sobol));
+        let points = sweep.unit_points();
+        assert_eq!(points.len(), 32);
+
+        for level in [0., 1.] {
+            let cell: Vec<Vec<f64>> = points
+                .iter()
+                .filter(|p| p[1] == level)
+                .map(|p| vec![p[0], p[2]])
+                .collect();
+            assert_eq!(cell.len(), 16);
+            assert_eq!(coverage(&cell).axis_coverage, vec![1., 1.]);
+        }
+        for (low, high) in points[..16].iter().zip(&points[16..]) {
+            assert_eq!((low[0], low[2]), (high[0], high[2]));
+        }
+    }
+
+    #[test]
+    fn space_filling_designs_cover_more_than_random() {
+        let design = |sampler| {
+            let mut sweep = Sweep::new(256, 3);
+            for name in ["a", "b", "c", "d"] {
+                sweep = sweep.with_axis(Axis::new(name, 0., 1., sampler));
+            }
+            sweep.points().coverage
+        };
+        let uniform = design(Sampler::Uniform);
+        for sampler in [Sampler::LatinHypercube, Sampler::Sobol, Sampler::Halton] {
+            let coverage = design(sampler);
+            assert_eq!(coverage.axis_coverage, vec![1.; 4]);
+            assert!(
+                coverage.axis_coverage.iter().sum::<f64>() > uniform.axis_coverage.iter().sum()
+            );
+            assert!(coverage.pair_coverage > uniform.pair_coverage);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+/// Describes how the search time of a shard shrinks as more threads work on it.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ThreadScaling {
+    /// Perfect scaling; `n` threads are `n` times as fast.
+    #[default]
+    Linear,
+    /// Amdahl's law: a fixed fraction of the work cannot be parallelized.
+    Amdahl { serial_fraction: f64 },
+    /// The Universal Scalability Law, which adds a coherency penalty to Amdahl's
+    /// contention term and allows throughput to degrade with too many threads.
+    Usl { contention: f64, coherency: f64 },
+    /// The vectors are split into chunks of at least `min_chunk_vectors` vectors that are
+    /// distributed over the threads in rounds, leaving threads idle for small shards.
+    Chunked { min_chunk_vectors: usize },
+}
+
+impl ThreadScaling {
+    /// The factor by which `num_threads` threads are faster than a single one when
+    /// searching `num_vectors` vectors.
+    pub fn speedup(&self, num_threads: usize, num_vectors: usize) -> f64 {
+        let n = num_threads as f64;
+        match *self {
+            ThreadScaling::Linear => n,
+            ThreadScaling::Amdahl { serial_fraction } => {
+                1. / (serial_fraction + (1. - serial_fraction) / n)
+            }
+            ThreadScaling::Usl {
+                contention,
+                coherency,
+            } => n / (1. + contention * (n - 1.) + coherency * n * (n - 1.)),
+            ThreadScaling::Chunked { min_chunk_vectors } => {
+                let chunks = (num_vectors / min_chunk_vectors.max(1)).max(1);
+                let rounds = chunks.div_ceil(num_threads);
+                chunks as f64 / rounds as f64
+            }
+        }
+    }
+
+    /// Fits Amdahl's law to measured `(num_threads, speedup)` pairs by least squares.
+    pub fn fit_amdahl(samples: &[(usize, f64)]) -> Self {
+        ThreadScaling::Amdahl {
+            serial_fraction: fit_serial_fraction(samples),
+        }
+    }
+
+    /// Fits the Universal Scalability Law to measured `(num_threads, speedup)` pairs by
+    /// least squares.
+    pub fn fit_usl(samples: &[(usize, f64)]) -> Self {
+        // Linearized: n / speedup - 1 = contention * (n - 1) + coherency * n * (n - 1)
+        let (mut aa, mut ab, mut bb, mut ay, mut by) = (0., 0., 0., 0., 0.);
+        for ((n, _), (x, y)) in samples.iter().zip(linearize(samples)) {
+            let a = x;
+            let b = *n as f64 * x;
+            aa += a * a;
+            ab += a * b;
+            bb += b * b;
+            ay += a * y;
+            by += b * y;
+        }
+
+        let determinant = aa * bb - ab * ab;
+        if determinant.abs() < f64::EPSILON {
+            return ThreadScaling::Usl {
+                contention: fit_serial_fraction(samples),
+                coherency: 0.,
+            };
+        }
+
+        ThreadScaling::Usl {
+            contention: ((ay * bb - by * ab) / determinant).max(0.),
+            coherency: ((aa * by - ab * ay) / determinant).max(0.),
+        }
+    }
+}
+
+fn fit_serial_fraction(samples: &[(usize, f64)]) -> f64 {
+    // Linearized: n / speedup - 1 = serial_fraction * (n - 1)
+    let (mut xy, mut xx) = (0., 0.);
+    for (x, y) in linearize(samples) {
+        xy += x * y;
+        xx += x * x;
+    }
+
+    let serial_fraction = if xx > 0. { xy / xx } else { 0. };
+    serial_fraction.clamp(0., 1.)
+}
+
+fn linearize(samples: &[(usize, f64)]) -> impl Iterator<Item = (f64, f64)> + '_ {
+    samples.iter().map(|&(n, speedup)| {
+        let n = n as f64;
+        (n - 1., n / speedup - 1.)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speedups_work() {
+        assert_eq!(ThreadScaling::Linear.speedup(8, 1000), 8.);
+        let amdahl = ThreadScaling::Amdahl {
+            serial_fraction: 0.1,
+        };
+        assert!((amdahl.speedup(8, 1000) - 1. / (0.1 + 0.9 / 8.)).abs() < 1e-12);
+
+        let chunked = ThreadScaling::Chunked {
+            min_chunk_vectors: 100,
+        };
+        assert_eq!(chunked.speedup(8, 300), 3.);
+        assert_eq!(chunked.speedup(8, 1000), 5.);
+        assert_eq!(chunked.speedup(8, 1600), 8.);
+    }
+
+    #[test]
+    fn usl_degrades_past_peak() {
+        let usl = ThreadScaling::Usl {
+            contention: 0.05,
+            coherency: 0.01,
+        };
+        assert!(usl.speedup(8, 0) > usl.speedup(2, 0));
+        assert!(usl.speedup(64, 0) < usl.speedup(8, 0));
+    }
+
+    #[test]
+    fn fitting_recovers_parameters() {
+        let truth = ThreadScaling::Usl {
+            contention: 0.05,
+            coherency: 0.002,
+        };
+        let samples: Vec<(usize, f64)> = [1, 2, 4, 8, 16, 32]
+            .iter()
+            .map(|&n| (n, truth.speedup(n, 0)))
+            .collect();
+
+        match ThreadScaling::fit_usl(&samples) {
+            ThreadScaling::Usl {
+                contention,
+                coherency,
+            } => {
+                assert!((contention - 0.05).abs() < 1e-9);
+                assert!((coherency - 0.002).abs() < 1e-9);
+            }
+            other => panic!("Unexpected model {other:?}"),
+        }
+
+        let amdahl = ThreadScaling::Amdahl {
+            serial_fraction: 0.2,
+        };
+        let samples: Vec<(usize, f64)> = [1, 2, 4, 8]
+            .iter()
+            .map(|&n| (n, amdahl.speedup(n, 0)))
+            .collect();
+        match ThreadScaling::fit_amdahl(&samples) {
+            ThreadScaling::Amdahl { serial_fraction } => {
+                assert!((serial_fraction - 0.2).abs() < 1e-9)
+            }
+            other => panic!("Unexpected model {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::filter::FilterPlan;
+use crate::index::{Index, ShardId};
+use crate::simulation::Simulation;
+use crate::timing::Seconds;
+
+/// Describes how the search tasks of shards co-located on a node share its threads.
+///
+/// Each shard's search is split into one task per thread of the node hosting it; see
+/// [`Simulation::node_of`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PoolScheduling {
+    /// Every shard has its own pool of `thread_count` threads.
+    #[default]
+    Dedicated,
+    /// The node's threads take tasks from a single queue, one shard after the other.
+    Fifo,
+    /// Tasks of all shards are interleaved and idle threads steal pending work, so
+    /// co-located shards progress together.
+    WorkStealing,
+    /// The node's threads are split into fixed partitions, one per shard. If there are
+    /// more shards than threads, shards take turns on their partition.
+    Partitioned,
+}
+
+impl PoolScheduling {
+    /// Schedules the shards co-located on a node with `num_threads` threads, given the
+    /// single-threaded search work of each shard, and returns the time at which each
+    /// shard completes.
+    pub fn schedule(&self, work: &[Seconds], num_threads: usize) -> Vec<Seconds> {
+        match self {
+            PoolScheduling::Dedicated => work.iter().map(|w| *w / num_threads).collect(),
+            PoolScheduling::Fifo => {
+                let tasks = (0..work.len())
+                    .flat_map(|shard| std::iter::repeat_n(shard, num_threads))
+                    .collect::<Vec<_>>();
+                list_schedule(work, &tasks, num_threads)
+            }
+            PoolScheduling::WorkStealing => {
+                let tasks = (0..num_threads)
+                    .flat_map(|_| 0..work.len())
+                    .collect::<Vec<_>>();
+                list_schedule(work, &tasks, num_threads)
+            }
+            PoolScheduling::Partitioned => {
+                let partitions = work.len().min(num_threads).max(1);
+                let mut partition_free_at = vec![0f64; partitions];
+                work.iter()
+                    .enumerate()
+                    .map(|(shard, w)| {
+                        let partition = shard % partitions;
+                        let threads = num_threads / partitions
+                            + usize::from(partition < num_threads % partitions);
+                        partition_free_at[partition] += **w / threads as f64;
+                        Seconds(partition_free_at[partition])
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Greedily assigns the tasks, in order, to the earliest idle thread. Each task carries
+/// an equal share of its shard's work.
+fn list_schedule(work: &[Seconds], tasks: &[usize], num_threads: usize) -> Vec<Seconds> {
+    let mut thread_free_at = vec![0f64; num_threads];
+    let mut completion = vec![0f64; work.len()];
+    for &shard in tasks {
+        let (thread, free_at) = thread_free_at
+            .iter()
+            .cloned()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+
+        let finish = free_at + *work[shard] / num_threads as f64;
+        thread_free_at[thread] = finish;
+        completion[shard] = completion[shard].max(finish);
+    }
+    completion.into_iter().map(Seconds).collect()
+}
+
+impl Simulation {
+    /// Determines the time at which each of the given shards finishes its search.
+    pub(crate) fn shard_completion_times(
+        &self,
+        index: &Index,
+        shard_ids: &[ShardId],
+    ) -> Vec<Seconds> {
+        self.filtered_completion_times(index, shard_ids, &FilterPlan::unfiltered())
+    }
+
+    /// Like [`Simulation::shard_completion_times`], for a query executed with the given
+    /// filter plan.
+    pub(crate) fn filtered_completion_times(
+        &self,
+        index: &Index,
+        shard_ids: &[ShardId],
+        filter: &FilterPlan,
+    ) -> Vec<Seconds> {
+        let mut search_times = Vec::with_capacity(shard_ids.len());
+        let mut nodes = Vec::with_capacity(shard_ids.len());
+        for shard_id in shard_ids {
+            let shard = index.shard(*shard_id).expect("Shard not found");
+            search_times.push(self.filtered_search_time(&shard, filter).0);
+            nodes.push(self.node_of(&shard));
+        }
+
+        if self.pool_scheduling == PoolScheduling::Dedicated {
+            return search_times;
+        }
+
+        let mut completion = vec![Seconds(0.); shard_ids.len()];
+        for node in 0..self.num_nodes {
+            let thread_count = self.node_threads(node);
+            let threading_cost = self.threading_cost * thread_count;
+            let positions: Vec<usize> =
+                (0..shard_ids.len()).filter(|&i| nodes[i] == node).collect();
+            let work: Vec<Seconds> = positions
+                .iter()
+                .map(|&i| Seconds(*search_times[i] - *threading_cost) * thread_count)
+                .collect();
+
+            let scheduled = self.pool_scheduling.schedule(&work, thread_count);
+            for (i, time) in positions.into_iter().zip(scheduled) {
+                completion[i] = time + threading_cost;
+            }
+        }
+        completion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifo_finishes_shards_one_after_another() {
+        let work = [Seconds(4.), Seconds(4.)];
+        let completion = PoolScheduling::Fifo.schedule(&work, 4);
+        assert_eq!(completion, vec![Seconds(1.), Seconds(2.)]);
+    }
+
+    #[test]
+    fn work_stealing_interleaves_shards() {
+        let work = [Seconds(4.), Seconds(4.)];
+        let completion = PoolScheduling::WorkStealing.schedule(&work, 4);
+        assert_eq!(completion, vec![Seconds(2.), Seconds(2.)]);
+    }
+
+    #[test]
+    fn partitioned_splits_threads() {
+        let work = [Seconds(4.), Seconds(2.), Seconds(2.)];
+        let completion = PoolScheduling::Partitioned.schedule(&work, 2);
+        assert_eq!(completion, vec![Seconds(4.), Seconds(2.), Seconds(6.)]);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::simulation::Simulation;
+use crate::timing::Seconds;
+
+/// Decides when the immutable segments of a shard are merged.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CompactionPolicy {
+    /// Segments are grouped into tiers of similar size; once a tier holds
+    /// `segments_per_tier` segments, they are merged into one segment of the next tier.
+    Tiered { segments_per_tier: usize },
+    /// Every level is a single segment that is `size_ratio` times larger than the
+    /// previous one. Flushed data is merged into level zero and cascades downwards
+    /// whenever a level outgrows its capacity.
+    Leveled { size_ratio: usize },
+    /// The newest segment is merged into its predecessor while it is at least
+    /// `1 / ratio` of the predecessor's size.
+    SizeRatio { ratio: f64 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SegmentConfig {
+    /// The number of vectors the mutable buffer holds before it is flushed.
+    pub buffer_capacity: usize,
+    pub policy: CompactionPolicy,
+}
+
+/// The segments a shard consists of.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SegmentLayout {
+    /// The sizes of the immutable segments, oldest first. Under leveled compaction the
+    /// position is the level and empty levels hold zero vectors.
+    segments: Vec<usize>,
+    /// The number of vectors in the mutable buffer.
+    buffer: usize,
+}
+
+#[derive(Debug)]
+pub struct SegmentReport {
+    /// The number of vectors written, including flushes and merges, per inserted vector.
+    pub write_amplification: f64,
+    /// The average number of segments searched per shard.
+    pub mean_segments: f64,
+    /// The query latency after ingestion.
+    pub latency: Seconds,
+}
+
+impl SegmentConfig {
+    pub fn new(buffer_capacity: usize, policy: CompactionPolicy) -> Self {
+        assert_ne!(buffer_capacity, 0);
+        match policy {
+            CompactionPolicy::Tiered { segments_per_tier } => assert!(segments_per_tier > 1),
+            CompactionPolicy::Leveled { size_ratio } => assert!(size_ratio > 1),
+            CompactionPolicy::SizeRatio { ratio } => assert!(ratio > 0.),
+        }
+        Self {
+            buffer_capacity,
+            policy,
+        }
+    }
+
+    /// The layout of a fully compacted shard of the given size.
+    pub fn initial_layout(&self, num_vectors: usize) -> SegmentLayout {
+        let mut segments = Vec::new();
+        if let CompactionPolicy::Leveled { .. } = self.policy {
+            let mut level = 0;
+            while num_vectors > self.level_capacity(level) {
+                level += 1;
+            }
+            segments.resize(level, 0);
+        }
+        if num_vectors > 0 {
+            segments.push(num_vectors);
+        }
+        SegmentLayout {
+            segments,
+            buffer: 0,
+        }
+    }
+
+    fn level_capacity(&self, level: usize) -> usize {
+        match self.policy {
+            CompactionPolicy::Leveled { size_ratio } => {
+                self.buffer_capacity * size_ratio.pow(level as u32 + 1)
+            }
+            _ => usize::MAX,
+        }
+    }
+}
+
+impl SegmentLayout {
+    pub fn new(segments: Vec<usize>) -> Self {
+        Self {
+            segments,
+            buffer: 0,
+        }
+    }
+
+    /// The number of non-empty immutable segments.
+    pub fn segment_count(&self) -> usize {
+        self.segments.iter().filter(|&&size| size > 0).count()
+    }
+
+    /// The number of segments a search visits, including the mutable buffer.
+    pub fn searched_segments(&self) -> usize {
+        self.segment_count() + usize::from(self.buffer > 0)
+    }
+
+    pub fn num_vectors(&self) -> usize {
+        self.segments.iter().sum::<usize>() + self.buffer
+    }
+
+    /// Inserts vectors into the buffer, flushing and compacting as needed. Returns the
+    /// number of vectors written to segments.
+    pub fn insert(&mut self, mut amount: usize, config: &SegmentConfig) -> usize {
+        let mut written = 0;
+        if self.buffer >= config.buffer_capacity {
+            written += self.flush(config);
+        }
+        while amount > 0 {
+            let take = amount.min(config.buffer_capacity - self.buffer);
+            self.buffer += take;
+            amount -= take;
+            if self.buffer == config.buffer_capacity {
+                written += self.flush(config);
+            }
+        }
+        written
+    }
+
+    /// Adds vectors inserted without a segment configuration to the buffer. They are
+    /// flushed by the next [`SegmentLayout::insert`].
+    pub fn append(&mut self, amount: usize) {
+        self.buffer += amount;
+    }
+
+    /// Removes deleted vectors, spread over the buffer and the segments in proportion
+    /// to their size.
+    pub fn remove(&mut self, amount: usize) {
+        let total = self.num_vectors();
+        assert!(amount <= total);
+        if amount == 0 {
+            return;
+        }
+
+        let mut remaining = amount;
+        for size in self.segments.iter_mut().chain([&mut self.buffer]) {
+            let share = (*size as u128 * amount as u128 / total as u128) as usize;
+            *size -= share;
+            remaining -= share;
+        }
+        // Rounding leaves fewer than one vector per segment, taken from the largest ones.
+        while remaining > 0 {
+            let largest = self
+                .segments
+                .iter_mut()
+                .chain([&mut self.buffer])
+                .max_by_key(|size| **size)
+                .unwrap();
+            let take = remaining.min(*largest);
+            *largest -= take;
+            remaining -= take;
+        }
+    }
+
+    fn flush(&mut self, config: &SegmentConfig) -> usize {
+        let flushed = std::mem::take(&mut self.buffer);
+        match config.policy {
+            CompactionPolicy::Tiered { segments_per_tier } => {
+                self.segments.push(flushed);
+                flushed + self.compact_tiers(config.buffer_capacity, segments_per_tier)
+            }
+            CompactionPolicy::Leveled { .. } => {
+                if self.segments.is_empty() {
+                    self.segments.push(0);
+                }
+                self.segments[0] += flushed;
+                let mut written = self.segments[0];
+                let mut level = 0;
+                while self.segments[level] > config.level_capacity(level) {
+                    if self.segments.len() == level + 1 {
+                        self.segments.push(0);
+                    }
+                    self.segments[level + 1] += std::mem::take(&mut self.segments[level]);
+                    written += self.segments[level + 1];
+                    level += 1;
+                }
+                written
+            }
+            CompactionPolicy::SizeRatio { ratio } => {
+                self.segments.push(flushed);
+                let mut written = flushed;
+                while let [.., older, newer] = self.segments[..] {
+                    if (newer as f64) * ratio < older as f64 {
+                        break;
+                    }
+                    self.segments.pop();
+                    *self.segments.last_mut().unwrap() += newer;
+                    written += older + newer;
+                }
+                written
+            }
+        }
+    }
+
+    fn compact_tiers(&mut self, buffer_capacity: usize, segments_per_tier: usize) -> usize {
+        let tier = |size: usize| {
+            (size as f64 / buffer_capacity as f64)
+                .max(1.)
+                .log(segments_per_tier as f64)
+                .floor() as usize
+        };
+
+        let mut written = 0;
+        loop {
+            let mut tiers: Vec<usize> = self.segments.iter().map(|&s| tier(s)).collect();
+            tiers.sort_unstable();
+            let full = tiers
+                .chunk_by(|a, b| a == b)
+                .find(|group| group.len() >= segments_per_tier)
+                .map(|group| group[0]);
+            let Some(full) = full else {
+                return written;
+            };
+
+            let (merged, kept): (Vec<usize>, Vec<usize>) =
+                self.segments.iter().partition(|&&s| tier(s) == full);
+            let size: usize = merged.iter().sum();
+            self.segments = kept;
+            self.segments.push(size);
+            written += size;
+        }
+    }
+}
+
+impl Simulation {
+    /// Inserts vectors spread evenly over the shards of an index through their segment
+    /// buffers, giving shards without a segment layout a fully compacted one first.
+    pub fn ingest_segmented(
+        &mut self,
+        index_id: usize,
+        num_inserts: usize,
+        config: &SegmentConfig,
+    ) -> SegmentReport {
+        let index = self.index_mut(index_id);
+        let shard_ids = index.shard_ids();
+        let mut written = 0;
+        let mut segments = 0;
+        for (i, &shard_id) in shard_ids.iter().enumerate() {
+            let amount =
+                num_inserts / shard_ids.len() + usize::from(i < num_inserts % shard_ids.len());
+            written += index.insert_segmented(shard_id, amount, config).unwrap();
+            let shard = index.shard(shard_id).unwrap();
+            segments += shard.segments.as_ref().unwrap().searched_segments();
+        }
+
+        SegmentReport {
+            write_amplification: written as f64 / num_inserts.max(1) as f64,
+            mean_segments: segments as f64 / shard_ids.len() as f64,
+            latency: self.simulate_find(index_id).duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Microseconds, Nanoseconds};
+
+    #[test]
+    fn policies_trade_segments_for_write_amplification() {
+        let tiered = SegmentConfig::new(
+            100,
+            CompactionPolicy::Tiered {
+                segments_per_tier: 10,
+            },
+        );
+        let leveled = SegmentConfig::new(100, CompactionPolicy::Leveled { size_ratio: 10 });
+
+        let mut tiered_layout = tiered.initial_layout(0);
+        let mut leveled_layout = leveled.initial_layout(0);
+        let tiered_written = tiered_layout.insert(50_050, &tiered);
+        let leveled_written = leveled_layout.insert(50_050, &leveled);
+
+        assert_eq!(tiered_layout.num_vectors(), 50_050);
+        assert_eq!(leveled_layout.num_vectors(), 50_050);
+        assert!(leveled_layout.searched_segments() < tiered_layout.searched_segments());
+        assert!(leveled_written > tiered_written);
+    }
+
+    #[test]
+    fn size_ratio_merges_similar_segments() {
+        let config = SegmentConfig::new(10, CompactionPolicy::SizeRatio { ratio: 2. });
+        let mut layout = SegmentLayout::new(vec![30]);
+        layout.insert(10, &config);
+        assert_eq!(layout.segments, vec![30, 10]);
+        layout.insert(10, &config);
+        assert_eq!(layout.segments, vec![50]);
+        layout.insert(5, &config);
+        assert_eq!(layout.searched_segments(), 2);
+    }
+
+    #[test]
+    fn deletes_shrink_segments_proportionally() {
+        let mut layout = SegmentLayout::new(vec![600, 300]);
+        layout.append(100);
+        layout.remove(500);
+        assert_eq!(layout.segments, vec![300, 150]);
+        assert_eq!(layout.buffer, 50);
+        layout.remove(1);
+        assert_eq!(layout.num_vectors(), 499);
+        assert_eq!(layout.searched_segments(), 3);
+    }
+
+    #[test]
+    fn segments_add_search_overhead() {
+        let mut simulation = SimulationBuilder::default()
+            .with_index(Index::new_evenly_split(0, 100_000, 4, 128))
+            .with_search_cost(Nanoseconds(0.2), Nanoseconds(0.))
+            .with_segment_search_cost(Microseconds(100.))
+            .build();
+        let before = simulation.simulate_find(0).duration;
+
+        let config = SegmentConfig::new(
+            500,
+            CompactionPolicy::Tiered {
+                segments_per_tier: 4,
+            },
+        );
+        let report = simulation.ingest_segmented(0, 10_000, &config);
+        assert_eq!(simulation.index(0).len(), 110_000);
+        assert!(report.mean_segments > 1.);
+        assert!(report.write_amplification >= 1.);
+        assert!(report.latency > before);
+    }
+}
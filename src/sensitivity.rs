@@ -0,0 +1,386 @@
+use crate::index::Index;
+use crate::sampling::{sobol, Axis, Sampler, Scale, Sweep};
+use crate::simulation::SimulationBuilder;
+use crate::timing::{Microseconds, Milliseconds, Nanoseconds};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// The cost of processing a single vector element in every run of the binary.
+pub const ELEMENT_COST: Nanoseconds = Nanoseconds(0.171326754);
+
+/// An input of [`SimulationBuilder`] varied by the binary's sweep.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Parameter {
+    ThreadCount,
+    ShardCount,
+    ElementCount,
+    VectorCost,
+    ScatterCost,
+    GatherCost,
+    ThreadOverhead,
+}
+
+impl Parameter {
+    /// Every parameter, in the order of the axes of [`sweep`].
+    pub const ALL: [Parameter; 7] = [
+        Parameter::ThreadCount,
+        Parameter::ShardCount,
+        Parameter::ElementCount,
+        Parameter::VectorCost,
+        Parameter::ScatterCost,
+        Parameter::GatherCost,
+        Parameter::ThreadOverhead,
+    ];
+
+    /// The axis the binary sweeps the parameter over. Counts cover `min..max`, costs
+    /// are given in the unit of [`RunInputs`].
+    pub fn axis(&self) -> Axis {
+        match self {
+            Parameter::ThreadCount => Axis::new("num_threads", 1., 32., Sampler::LatinHypercube),
+            Parameter::ShardCount => Axis::new("num_shards", 1., 40., Sampler::LatinHypercube),
+            Parameter::ElementCount => {
+                Axis::new("num_elements", 10., 1_000_000., Sampler::Sobol).log()
+            }
+            Parameter::VectorCost => Axis::new("cost_per_vector", 0., 50., Sampler::Sobol),
+            Parameter::ScatterCost => Axis::new("cost_per_scatter", 0., 100., Sampler::Sobol),
+            Parameter::GatherCost => Axis::new("cost_per_gather", 0., 100., Sampler::Sobol),
+            Parameter::ThreadOverhead => Axis::new("thread_overhead", 0., 100., Sampler::Sobol),
+        }
+    }
+
+    fn is_count(&self) -> bool {
+        matches!(
+            self,
+            Parameter::ThreadCount | Parameter::ShardCount | Parameter::ElementCount
+        )
+    }
+}
+
+/// The sweep run by the binary, with one axis per parameter in the order of
+/// [`Parameter::ALL`].
+pub fn sweep(samples: usize, seed: u64) -> Sweep {
+    Parameter::ALL
+        .iter()
+        .fold(Sweep::new(samples, seed), |sweep, parameter| {
+            sweep.with_axis(parameter.axis())
+        })
+}
+
+/// The inputs of a run, decoded from one value per axis of [`sweep`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunInputs {
+    pub num_threads: usize,
+    pub num_shards: usize,
+    pub num_elements: usize,
+    pub cost_per_vector: Nanoseconds,
+    pub cost_per_scatter: Milliseconds,
+    pub cost_per_gather: Milliseconds,
+    pub thread_overhead: Microseconds,
+}
+
+impl RunInputs {
+    /// Rounds counts down, keeping them below the maximum of their axis.
+    pub fn new(axes: &[Axis], values: &[f64]) -> Self {
+        assert_eq!(axes.len(), Parameter::ALL.len());
+        assert_eq!(values.len(), axes.len());
+        let value = |parameter: Parameter| values[parameter as usize];
+        let count = |parameter: Parameter| {
+            let max = axes[parameter as usize].max as usize;
+            (value(parameter) as usize).min(max - 1)
+        };
+        Self {
+            num_threads: count(Parameter::ThreadCount),
+            num_shards: count(Parameter::ShardCount),
+            num_elements: count(Parameter::ElementCount),
+            cost_per_vector: Nanoseconds(value(Parameter::VectorCost)),
+            cost_per_scatter: Milliseconds(value(Parameter::ScatterCost)),
+            cost_per_gather: Milliseconds(value(Parameter::GatherCost)),
+            thread_overhead: Microseconds(value(Parameter::ThreadOverhead)),
+        }
+    }
+}
+
+/// The elementary effects of a parameter found by Morris screening.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MorrisEffect {
+    /// The mean absolute elementary effect, a measure of the parameter's overall influence.
+    pub mu_star: f64,
+    /// The standard deviation of the elementary effects, high for parameters that act
+    /// nonlinearly or through interactions.
+    pub sigma: f64,
+}
+
+/// The variance-based sensitivity of the output to a parameter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SobolIndex {
+    /// The share of the output variance caused by the parameter alone.
+    pub first_order: f64,
+    /// The share of the output variance caused by the parameter, including all of its
+    /// interactions with other parameters.
+    pub total: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParameterSensitivity {
+    pub parameter: Parameter,
+    pub morris: MorrisEffect,
+    pub sobol: SobolIndex,
+}
+
+#[derive(Debug, Clone)]
+pub struct SensitivityReport {
+    /// One entry per parameter, ordered by decreasing total Sobol index.
+    pub parameters: Vec<ParameterSensitivity>,
+    /// The variance of the duration over the sampled parameter space.
+    pub variance: f64,
+}
+
+/// Maps points of the unit hypercube onto the axes of the binary's sweep and returns the
+/// duration of a single index, holding the element count evenly split into the shard
+/// count.
+#[derive(Debug, Clone)]
+pub struct DurationModel {
+    pub vector_length: usize,
+    /// One axis per parameter, in the order of [`Parameter::ALL`].
+    pub axes: Vec<Axis>,
+}
+
+impl DurationModel {
+    /// Varies every parameter over the axis swept by the binary.
+    pub fn new(vector_length: usize) -> Self {
+        Self {
+            vector_length,
+            axes: sweep(1, 0).axes,
+        }
+    }
+
+    /// Replaces the range of a parameter, keeping the scale of its axis.
+    pub fn with_range(mut self, parameter: Parameter, min: f64, max: f64) -> Self {
+        if parameter.is_count() {
+            assert!(1. <= min && min < max, "Count ranges cover min..max");
+        }
+        assert!(min <= max);
+        let axis = &mut self.axes[parameter as usize];
+        if axis.scale == Scale::Log {
+            assert!(min > 0.);
+        }
+        axis.min = min;
+        axis.max = max;
+        self
+    }
+
+    /// The simulated duration at a point of the unit hypercube, one coordinate per axis.
+    pub fn duration(&self, point: &[f64]) -> f64 {
+        let values: Vec<f64> = self
+            .axes
+            .iter()
+            .zip(point)
+            .map(|(a, &u)| a.value(u))
+            .collect();
+        let inputs = RunInputs::new(&self.axes, &values);
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_evenly_split(
+                0,
+                inputs.num_elements,
+                inputs.num_shards,
+                self.vector_length,
+            ))
+            .with_search_cost(ELEMENT_COST, inputs.cost_per_vector)
+            .with_scatter_gather_cost(inputs.cost_per_scatter, inputs.cost_per_gather)
+            .with_threads(inputs.num_threads, inputs.thread_overhead)
+            .build();
+        *simulation.simulate_find(0).duration
+    }
+
+    /// Runs Morris screening with `trajectories` trajectories and estimates Sobol
+    /// indices from `samples` quasi-random base samples.
+    pub fn analyze(&self, trajectories: usize, samples: usize, seed: u64) -> SensitivityReport {
+        let dimensions = self.axes.len();
+        let model = |point: &[f64]| self.duration(point);
+        let morris = morris_screening(model, dimensions, trajectories, 4, seed);
+        let (sobol, variance) = sobol_indices(model, dimensions, samples);
+
+        let mut parameters: Vec<ParameterSensitivity> = Parameter::ALL
+            .into_iter()
+            .zip(morris.into_iter().zip(sobol))
+            .map(|(parameter, (morris, sobol))| ParameterSensitivity {
+                parameter,
+                morris,
+                sobol,
+            })
+            .collect();
+        parameters.sort_by(|a, b| b.sobol.total.total_cmp(&a.sobol.total));
+        SensitivityReport {
+            parameters,
+            variance,
+        }
+    }
+}
+
+/// Estimates the elementary effects of every input of a model on the unit hypercube.
+///
+/// Each trajectory starts at a random point of a grid with `levels` levels per input and
+/// changes one input at a time, in random order, by `levels / (2 * (levels - 1))`.
+pub fn morris_screening<F>(
+    model: F,
+    dimensions: usize,
+    trajectories: usize,
+    levels: usize,
+    seed: u64,
+) -> Vec<MorrisEffect>
+where
+    F: Fn(&[f64]) -> f64,
+{
+    assert!(levels >= 2 && levels.is_multiple_of(2));
+    assert_ne!(trajectories, 0);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let delta = levels as f64 / (2. * (levels - 1) as f64);
+    // Start points whose coordinates can be stepped up without leaving the hypercube.
+    let start_levels = levels / 2;
+
+    let mut effects = vec![Vec::with_capacity(trajectories); dimensions];
+    let mut order: Vec<usize> = (0..dimensions).collect();
+    for _ in 0..trajectories {
+        let mut point: Vec<f64> = (0..dimensions)
+            .map(|_| rng.gen_range(0..start_levels) as f64 / (levels - 1) as f64)
+            .collect();
+        let mut output = model(&point);
+        order.shuffle(&mut rng);
+        for &dimension in &order {
+            point[dimension] += delta;
+            let next = model(&point);
+            effects[dimension].push((next - output) / delta);
+            output = next;
+        }
+    }
+
+    effects
+        .into_iter()
+        .map(|effects| {
+            let n = effects.len() as f64;
+            let mean = effects.iter().sum::<f64>() / n;
+            let variance = effects.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / n;
+            MorrisEffect {
+                mu_star: effects.iter().map(|e| e.abs()).sum::<f64>() / n,
+                sigma: variance.sqrt(),
+            }
+        })
+        .collect()
+}
+
+/// Estimates the first-order and total Sobol indices of every input of a model on the
+/// unit hypercube using Saltelli's scheme, with both sample matrices taken from a Sobol
+/// sequence. Also returns the output variance.
+pub fn sobol_indices<F>(model: F, dimensions: usize, samples: usize) -> (Vec<SobolIndex>, f64)
+where
+    F: Fn(&[f64]) -> f64,
+{
+    assert_ne!(samples, 0);
+    // The first point of the sequence sits on the corner of the hypercube.
+    let points = sobol(samples + 1, 2 * dimensions);
+    let (a, b): (Vec<&[f64]>, Vec<&[f64]>) =
+        points[1..].iter().map(|p| p.split_at(dimensions)).unzip();
+    let f_a: Vec<f64> = a.iter().map(|p| model(p)).collect();
+    let f_b: Vec<f64> = b.iter().map(|p| model(p)).collect();
+
+    let n = samples as f64;
+    let all = f_a.iter().chain(&f_b);
+    let mean = all.clone().sum::<f64>() / (2. * n);
+    let variance = all.map(|f| (f - mean).powi(2)).sum::<f64>() / (2. * n);
+
+    let indices = (0..dimensions)
+        .map(|dimension| {
+            let (mut first_order, mut total) = (0., 0.);
+            for j in 0..samples {
+                let mut mixed = a[j].to_vec();
+                mixed[dimension] = b[j][dimension];
+                let f_mixed = model(&mixed);
+                first_order += f_b[j] * (f_mixed - f_a[j]);
+                total += (f_a[j] - f_mixed).powi(2);
+            }
+            if variance > 0. {
+                SobolIndex {
+                    first_order: first_order / n / variance,
+                    total: total / (2. * n) / variance,
+                }
+            } else {
+                SobolIndex {
+                    first_order: 0.,
+                    total: 0.,
+                }
+            }
+        })
+        .collect();
+    (indices, variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_of_additive_model() {
+        // The inputs contribute 16, 4 and 0 parts of the variance.
+        let model = |x: &[f64]| 4. * x[0] + 2. * x[1];
+        let (indices, variance) = sobol_indices(model, 3, 1024);
+        assert!((variance - 20. / 12.).abs() < 0.01);
+        assert!((indices[0].first_order - 0.8).abs() < 0.02);
+        assert!((indices[1].first_order - 0.2).abs() < 0.02);
+        assert!(indices[2].total.abs() < 1e-9);
+
+        let effects = morris_screening(model, 3, 10, 4, 1);
+        assert!((effects[0].mu_star - 4.).abs() < 1e-9);
+        assert!((effects[1].mu_star - 2.).abs() < 1e-9);
+        assert_eq!(effects[2].mu_star, 0.);
+        assert!(effects[0].sigma < 1e-9);
+    }
+
+    #[test]
+    fn interactions_show_in_total_index() {
+        let model = |x: &[f64]| x[0] * x[1];
+        let (indices, _) = sobol_indices(model, 2, 1024);
+        assert!(indices[0].total > indices[0].first_order + 0.05);
+        let effects = morris_screening(model, 2, 20, 4, 1);
+        assert!(effects[0].sigma > 0.);
+    }
+
+    #[test]
+    fn fixed_parameters_do_not_matter() {
+        let model = DurationModel::new(128)
+            .with_range(Parameter::ScatterCost, 0., 0.)
+            .with_range(Parameter::GatherCost, 0., 0.)
+            .with_range(Parameter::ThreadCount, 1., 2.)
+            .with_range(Parameter::ElementCount, 1e6, 1e6 + 1.);
+        let report = model.analyze(10, 64, 3);
+        assert_eq!(report.parameters.len(), 7);
+        let top = report.parameters[0];
+        assert_eq!(top.parameter, Parameter::ShardCount);
+        for sensitivity in &report.parameters[3..] {
+            assert_eq!(sensitivity.sobol.total, 0.);
+            assert_eq!(sensitivity.morris.mu_star, 0.);
+        }
+
+        // The scatter-gather overhead, which grows with the shard count, dwarfs the
+        // search of even the largest index of the sweep.
+        let report = DurationModel::new(128).analyze(10, 64, 3);
+        let top: Vec<Parameter> = report.parameters[..3].iter().map(|p| p.parameter).collect();
+        assert_eq!(top[0], Parameter::ShardCount);
+        assert!(top.contains(&Parameter::ScatterCost) && top.contains(&Parameter::GatherCost));
+        assert!(report.parameters[3..].iter().all(|p| p.sobol.total < 0.01));
+    }
+
+    #[test]
+    fn run_inputs_follow_sweep_axes() {
+        let sweep = sweep(1, 0);
+        let values: Vec<f64> = sweep.axes.iter().map(|axis| axis.value(1.)).collect();
+        let inputs = RunInputs::new(&sweep.axes, &values);
+        assert_eq!(
+            (inputs.num_threads, inputs.num_shards, inputs.num_elements),
+            (31, 39, 999_999)
+        );
+        assert_eq!(*inputs.cost_per_scatter, 100.);
+        // The element count is spread over orders of magnitude.
+        assert!((sweep.axes[2].value(0.5) - 10f64.powf(3.5)).abs() < 1e-6);
+    }
+}
@@ -1,16 +1,33 @@
-use crate::index::Index;
+use crate::filter::FilterPlan;
+use crate::hardware::{HardwareProfile, VECTOR_ELEMENT_BYTES};
+use crate::index::{Index, IndexAssignment, ShardId};
+use crate::network::NetworkModel;
+use crate::routing::{RecallModel, Routing};
+use crate::scaling::ThreadScaling;
+use crate::scheduler::PoolScheduling;
 use crate::timing::Seconds;
+use crate::topology::{AggregationTree, ScatterModel};
 use std::collections::{BinaryHeap, HashMap};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Simulation {
     indexes: HashMap<usize, Index>,
     search_cost_per_vector_element: Seconds,
-    search_cost_per_vector: Seconds,
+    pub(crate) search_cost_per_vector: Seconds,
     search_cost_per_scatter: Seconds,
     search_cost_per_gather: Seconds,
     pub thread_count: usize,
-    threading_cost: Seconds,
+    pub(crate) threading_cost: Seconds,
+    pub(crate) routing: Routing,
+    pub num_nodes: usize,
+    aggregation: AggregationTree,
+    scatter_model: ScatterModel,
+    serialization_cost: Seconds,
+    network: Option<NetworkModel>,
+    thread_scaling: ThreadScaling,
+    pub(crate) pool_scheduling: PoolScheduling,
+    pub(crate) node_profiles: Vec<HardwareProfile>,
+    segment_search_cost: Seconds,
 }
 
 pub struct SimulationBuilder {
@@ -21,6 +38,16 @@ pub struct SimulationBuilder {
     search_cost_per_gather: Seconds,
     thread_count: usize,
     threading_cost: Seconds,
+    routing: Routing,
+    num_nodes: usize,
+    aggregation: AggregationTree,
+    scatter_model: ScatterModel,
+    serialization_cost: Seconds,
+    network: Option<NetworkModel>,
+    thread_scaling: ThreadScaling,
+    pool_scheduling: PoolScheduling,
+    node_profiles: Vec<HardwareProfile>,
+    segment_search_cost: Seconds,
 }
 
 impl Default for SimulationBuilder {
@@ -33,6 +60,16 @@ impl Default for SimulationBuilder {
             search_cost_per_gather: Seconds::default(),
             thread_count: 1,
             threading_cost: Seconds::default(),
+            routing: Routing::default(),
+            num_nodes: 1,
+            aggregation: AggregationTree::flat(),
+            scatter_model: ScatterModel::default(),
+            serialization_cost: Seconds::default(),
+            network: None,
+            thread_scaling: ThreadScaling::default(),
+            pool_scheduling: PoolScheduling::default(),
+            node_profiles: Vec::new(),
+            segment_search_cost: Seconds::default(),
         }
     }
 }
@@ -74,6 +111,82 @@ impl SimulationBuilder {
         self
     }
 
+    /// Selects how the search time of a shard scales with the number of threads.
+    pub fn with_thread_scaling(mut self, scaling: ThreadScaling) -> Self {
+        self.thread_scaling = scaling;
+        self
+    }
+
+    /// Selects how co-located shards share the threads of their node.
+    pub fn with_pool_scheduling(mut self, scheduling: PoolScheduling) -> Self {
+        self.pool_scheduling = scheduling;
+        self
+    }
+
+    /// Sets the number of nodes shared by all indexes, each running `thread_count` threads.
+    pub fn with_nodes(mut self, num_nodes: usize) -> Self {
+        assert_ne!(num_nodes, 0);
+        self.num_nodes = num_nodes;
+        self
+    }
+
+    /// Runs the cluster on nodes with the given hardware, one node per profile. Each node
+    /// searches with its own per-element cost and at most as many threads as it has cores.
+    pub fn with_node_profiles(mut self, profiles: Vec<HardwareProfile>) -> Self {
+        assert!(!profiles.is_empty());
+        self.num_nodes = profiles.len();
+        self.node_profiles = profiles;
+        self
+    }
+
+    /// Only fans out to the `nprobe_shards` shards closest to the query instead of
+    /// scattering to every shard; see [`Routing::Partitioned`].
+    pub fn with_shard_pruning(mut self, nprobe_shards: usize, recall: RecallModel) -> Self {
+        assert_ne!(nprobe_shards, 0);
+        self.routing = Routing::Partitioned {
+            nprobe_shards,
+            recall,
+        };
+        self
+    }
+
+    /// Routes scatter and gather through intermediate aggregators instead of having
+    /// the coordinator talk to every shard directly.
+    pub fn with_aggregation_tree(mut self, tree: AggregationTree) -> Self {
+        self.aggregation = tree;
+        self
+    }
+
+    /// Selects how scatter RPCs are issued and what each request costs the sender to encode.
+    pub fn with_scatter_model<S>(mut self, model: ScatterModel, serialization: S) -> Self
+    where
+        S: Into<Seconds>,
+    {
+        if let ScatterModel::Bounded { max_in_flight } = model {
+            assert_ne!(max_in_flight, 0);
+        }
+        self.scatter_model = model;
+        self.serialization_cost = serialization.into();
+        self
+    }
+
+    /// Adds payload-dependent transfer times to every scatter-gather round trip.
+    pub fn with_network(mut self, network: NetworkModel) -> Self {
+        assert!(!network.tiers.is_empty());
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the fixed cost of searching one segment of a shard. It only applies to
+    /// shards with a segment layout.
+    pub fn with_segment_search_cost<C>(mut self, cost: C) -> Self
+    where
+        C: Into<Seconds>,
+    {
+        self.segment_search_cost = cost.into();
+        self
+    }
+
     pub fn build(self) -> Simulation {
         Simulation {
             indexes: self.indexes,
@@ -83,6 +196,16 @@ impl SimulationBuilder {
             search_cost_per_gather: self.search_cost_per_gather,
             thread_count: self.thread_count,
             threading_cost: self.threading_cost,
+            routing: self.routing,
+            num_nodes: self.num_nodes,
+            aggregation: self.aggregation,
+            scatter_model: self.scatter_model,
+            serialization_cost: self.serialization_cost,
+            network: self.network,
+            thread_scaling: self.thread_scaling,
+            pool_scheduling: self.pool_scheduling,
+            node_profiles: self.node_profiles,
+            segment_search_cost: self.segment_search_cost,
         }
     }
 }
@@ -93,41 +216,192 @@ pub struct SimulationResult {
     pub duration: Seconds,
     /// The total duration, as if everything executed sequentially.
     pub duration_total: Seconds,
+    /// The number of shards the query was sent to.
+    pub shards_visited: usize,
+    /// The estimated fraction of true nearest neighbours found.
+    pub recall: f64,
 }
 
 impl Simulation {
     pub fn simulate_find(&self, index_id: usize) -> SimulationResult {
-        let index = self.indexes.get(&index_id).expect("Index not found");
+        let index = self.index(index_id);
+        let shard_ids = self.routing.select_shards(index, None);
+        self.simulate_shards(index, &shard_ids, &FilterPlan::unfiltered())
+    }
 
+    /// Like [`Simulation::simulate_find`], but routes the given query vector to its
+    /// nearest shards when shard pruning is enabled.
+    pub fn simulate_find_query(&self, index_id: usize, query: &[f32]) -> SimulationResult {
+        let index = self.index(index_id);
+        let shard_ids = self.routing.select_shards(index, Some(query));
+        self.simulate_shards(index, &shard_ids, &FilterPlan::unfiltered())
+    }
+
+    pub(crate) fn simulate_shards(
+        &self,
+        index: &Index,
+        shard_ids: &[ShardId],
+        filter: &FilterPlan,
+    ) -> SimulationResult {
         let mut search_time_max = Seconds(0.);
         let mut search_time_total = Seconds(0.);
-        let mut scatter_time = Seconds(0.);
-        let mut gather_time = Seconds(0.);
-
-        for shard in index.into_iter() {
-            scatter_time += self.search_cost_per_scatter;
-            gather_time += self.search_cost_per_gather;
-            let threading_cost = self.threading_cost * self.thread_count;
-
-            let search_time_per_vector = self.search_cost_per_vector_element * shard.vector_length;
-            let base_search_time =
-                (search_time_per_vector + self.search_cost_per_vector) * shard.num_vectors;
-
-            let threaded_search_time = base_search_time / self.thread_count + threading_cost;
-            let threaded_search_time_total = base_search_time + threading_cost;
+        let mut visited_vectors = 0;
 
-            search_time_max = Seconds(search_time_max.0.max(threaded_search_time.0));
+        for shard_id in shard_ids {
+            let shard = index.shard(*shard_id).expect("Shard not found");
+            visited_vectors += shard.num_vectors;
+            let (_, threaded_search_time_total) = self.filtered_search_time(&shard, filter);
             search_time_total += threaded_search_time_total;
         }
 
-        let overhead = scatter_time + gather_time;
+        for completion_time in self.filtered_completion_times(index, shard_ids, filter) {
+            search_time_max = Seconds(search_time_max.0.max(completion_time.0));
+        }
+
+        let (overhead, overhead_total) =
+            self.scatter_gather_overhead(shard_ids.len(), index.vector_length);
         let duration = search_time_max + overhead;
-        let duration_sequential = search_time_total + overhead;
+        let duration_sequential = search_time_total + overhead_total;
 
         SimulationResult {
             duration,
             duration_total: duration_sequential,
+            shards_visited: shard_ids.len(),
+            recall: self.routing.recall(index, visited_vectors) * filter.completeness,
+        }
+    }
+
+    /// The node hosting a shard. Shards are assigned to nodes round-robin by shard ID.
+    pub fn node_of(&self, shard: &IndexAssignment) -> usize {
+        (shard.shard_id.get() - 1) % self.num_nodes
+    }
+
+    pub fn node_profiles(&self) -> &[HardwareProfile] {
+        &self.node_profiles
+    }
+
+    /// The number of threads a node searches a shard with.
+    pub fn node_threads(&self, node: usize) -> usize {
+        match self.node_profiles.get(node) {
+            Some(profile) => self.thread_count.min(profile.cores),
+            None => self.thread_count,
+        }
+    }
+
+    /// Determines the time it takes to search a single shard using all threads, as well
+    /// as the time it would take on a single thread.
+    pub(crate) fn shard_search_time(&self, shard: &IndexAssignment) -> (Seconds, Seconds) {
+        self.filtered_search_time(shard, &FilterPlan::unfiltered())
+    }
+
+    /// Like [`Simulation::shard_search_time`], but only computes distances to the
+    /// fraction of vectors the filter plan scans, plus the cost of its predicate checks.
+    pub(crate) fn filtered_search_time(
+        &self,
+        shard: &IndexAssignment,
+        filter: &FilterPlan,
+    ) -> (Seconds, Seconds) {
+        let node = self.node_of(shard);
+        let thread_count = self.node_threads(node);
+        let threading_cost = self.threading_cost * thread_count;
+        let scanned_vectors = (shard.num_vectors as f64 * filter.scanned_fraction).ceil() as usize;
+        let predicate_checks =
+            (shard.num_vectors as f64 * filter.predicate_fraction).ceil() as usize;
+
+        let (cost_per_element, memory_time) = match self.node_profiles.get(node) {
+            Some(profile) => {
+                let bytes = scanned_vectors * shard.vector_length * VECTOR_ELEMENT_BYTES;
+                (
+                    profile.cost_per_vector_element,
+                    Seconds(bytes as f64 / profile.memory_bandwidth),
+                )
+            }
+            None => (self.search_cost_per_vector_element, Seconds(0.)),
+        };
+
+        let search_time_per_vector = cost_per_element * shard.vector_length;
+        let segment_cost = match &shard.segments {
+            Some(layout) => self.segment_search_cost * layout.searched_segments(),
+            None => Seconds(0.),
+        };
+        let base_search_time = (search_time_per_vector + self.search_cost_per_vector)
+            * scanned_vectors
+            + filter.predicate_cost * predicate_checks
+            + segment_cost;
+
+        let speedup = self.thread_scaling.speedup(thread_count, scanned_vectors);
+        let compute_time = Seconds(*base_search_time / speedup);
+        let threaded_search_time = Seconds(compute_time.0.max(memory_time.0)) + threading_cost;
+        let threaded_search_time_total =
+            Seconds(base_search_time.0.max(memory_time.0)) + threading_cost;
+        (threaded_search_time, threaded_search_time_total)
+    }
+
+    /// Determines the scatter-gather overhead along the critical path through the
+    /// aggregation tree, as well as the overhead summed over all hops.
+    pub(crate) fn scatter_gather_overhead(
+        &self,
+        num_shards: usize,
+        vector_length: usize,
+    ) -> (Seconds, Seconds) {
+        let mut critical = Seconds(0.);
+        let mut total = Seconds(0.);
+        for level in self.aggregation.levels(num_shards) {
+            let requests: Vec<Seconds> = (0..level.max_children)
+                .map(|child| self.scatter_round_trip(child, vector_length))
+                .collect();
+            critical += self
+                .scatter_model
+                .duration(&requests, self.serialization_cost);
+            critical += self.search_cost_per_gather * level.max_children;
+
+            for child in 0..level.total_children {
+                total += self.scatter_round_trip(child, vector_length);
+            }
+            total += (self.search_cost_per_gather + self.serialization_cost) * level.total_children;
+
+            if level.is_aggregator {
+                critical += self.aggregation.merge_cost_per_child * level.max_children;
+                total += self.aggregation.merge_cost_per_child * level.total_children;
+            }
         }
+        (critical, total)
+    }
+
+    /// The time a single scatter request to the `child`-th child spends in flight.
+    fn scatter_round_trip(&self, child: usize, vector_length: usize) -> Seconds {
+        match &self.network {
+            None => self.search_cost_per_scatter,
+            Some(network) => {
+                self.search_cost_per_scatter + network.round_trip(child, vector_length)
+            }
+        }
+    }
+
+    /// Re-shards the index into `1..=max_shards` evenly sized shards and returns the
+    /// shard count with the lowest simulated duration, along with its result.
+    pub fn optimal_shard_count(
+        &self,
+        index_id: usize,
+        max_shards: usize,
+    ) -> (usize, SimulationResult) {
+        let index = self.index(index_id);
+        (1..=max_shards)
+            .map(|num_shards| {
+                let candidate = Index::new_evenly_split(
+                    index.index_id,
+                    index.num_vectors,
+                    num_shards,
+                    index.vector_length,
+                );
+                let shard_ids = self.routing.select_shards(&candidate, None);
+                (
+                    num_shards,
+                    self.simulate_shards(&candidate, &shard_ids, &FilterPlan::unfiltered()),
+                )
+            })
+            .min_by(|a, b| a.1.duration.0.total_cmp(&b.1.duration.0))
+            .expect("At least one shard is required")
     }
 
     pub fn index_id(&self) -> Vec<usize> {
@@ -138,20 +412,41 @@ impl Simulation {
             .into_sorted_vec()
     }
 
+    /// A copy of the simulation's configuration without any of its indexes.
+    pub(crate) fn without_indexes(&self) -> Simulation {
+        Simulation {
+            indexes: HashMap::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Adds an index, replacing any index with the same ID.
+    pub(crate) fn insert_index(&mut self, index: Index) {
+        self.indexes.insert(index.index_id, index);
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn index(&self, index_id: usize) -> &Index {
         self.indexes.get(&index_id).expect("Index not found")
     }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn index_mut(&mut self, index_id: usize) -> &mut Index {
+        self.indexes.get_mut(&index_id).expect("Index not found")
+    }
 }
 
-impl Into<Simulation> for SimulationBuilder {
-    fn into(self) -> Simulation {
-        self.build()
+impl From<SimulationBuilder> for Simulation {
+    fn from(builder: SimulationBuilder) -> Self {
+        builder.build()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hardware::proportional_shard_sizes;
+    use crate::network::Link;
     use crate::timing::{Microseconds, Milliseconds, Nanoseconds};
 
     #[test]
@@ -216,7 +511,161 @@ mod tests {
                 assignment.len(),
                 result.duration
             );
-            let duration = result;
         }
     }
+
+    #[test]
+    fn shard_pruning_reduces_fan_out() {
+        let index = Index::new_from_shards(0, &[1_000_000; 8], 2);
+        for (i, shard_id) in index.shard_ids().into_iter().enumerate() {
+            index.set_centroid(shard_id, vec![i as f32, 0.]).unwrap();
+        }
+
+        let full = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 8], 2))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .build();
+        let pruned = SimulationBuilder::default()
+            .with_index(index)
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .with_shard_pruning(2, RecallModel::Clustered { locality: 8. })
+            .build();
+
+        let full = full.simulate_find(0);
+        let pruned = pruned.simulate_find_query(0, &[0.2, 0.]);
+        assert_eq!(full.shards_visited, 8);
+        assert_eq!(full.recall, 1.);
+        assert_eq!(pruned.shards_visited, 2);
+        assert!(pruned.duration < full.duration);
+        assert!(pruned.recall > 0.85 && pruned.recall < 1.);
+    }
+
+    #[test]
+    fn parallel_scatter_favours_more_shards() {
+        let build = |model: ScatterModel| {
+            SimulationBuilder::default()
+                .with_index(Index::new(0, 20_000_000, 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_scatter_gather_cost(Milliseconds(20.), Microseconds(50.))
+                .with_scatter_model(model, Microseconds(10.))
+                .build()
+        };
+
+        let (sequential, _) = build(ScatterModel::Sequential).optimal_shard_count(0, 40);
+        let (bounded, _) =
+            build(ScatterModel::Bounded { max_in_flight: 4 }).optimal_shard_count(0, 40);
+        let (parallel, _) = build(ScatterModel::Parallel).optimal_shard_count(0, 40);
+        assert!(sequential < bounded);
+        assert!(bounded <= parallel);
+    }
+
+    #[test]
+    fn network_payload_affects_duration() {
+        let build = |vector_length: usize| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, &[0; 4], vector_length))
+                .with_scatter_gather_cost(Microseconds(200.), Microseconds(200.))
+                .with_network(
+                    NetworkModel::new(Link::new(Microseconds(50.), 1.25e8))
+                        .with_tier(Link::new(Milliseconds(1.), 1.25e7)),
+                )
+                .build()
+        };
+
+        let small = build(384).simulate_find(0);
+        let large = build(1536).simulate_find(0);
+        assert!(large.duration > small.duration);
+
+        let cross_zone: Seconds = Milliseconds(1.).into();
+        assert!(small.duration > Seconds(1.6e-3) + cross_zone * 4);
+    }
+
+    #[test]
+    fn thread_scaling_affects_duration() {
+        let build = |scaling: ThreadScaling| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, &[1_000_000], 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_threads(16, Microseconds(10.))
+                .with_thread_scaling(scaling)
+                .build()
+                .simulate_find(0)
+        };
+
+        let linear = build(ThreadScaling::Linear);
+        let amdahl = build(ThreadScaling::Amdahl {
+            serial_fraction: 0.05,
+        });
+        let usl = build(ThreadScaling::Usl {
+            contention: 0.05,
+            coherency: 0.01,
+        });
+        assert!(linear.duration < amdahl.duration);
+        assert!(amdahl.duration < usl.duration);
+        assert_eq!(linear.duration_total, usl.duration_total);
+    }
+
+    #[test]
+    fn shared_pools_interleave_co_located_shards() {
+        let build = |scheduling: PoolScheduling| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, &[1_000_000; 4], 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_threads(8, Microseconds(10.))
+                .with_nodes(2)
+                .with_pool_scheduling(scheduling)
+                .build()
+                .simulate_find(0)
+        };
+
+        let dedicated = build(PoolScheduling::Dedicated);
+        let fifo = build(PoolScheduling::Fifo);
+        let stealing = build(PoolScheduling::WorkStealing);
+        let partitioned = build(PoolScheduling::Partitioned);
+        assert!(fifo.duration > dedicated.duration);
+        assert!((*fifo.duration - *stealing.duration).abs() < 1e-9);
+        assert!((*partitioned.duration - *stealing.duration).abs() < 1e-9);
+        assert_eq!(dedicated.duration_total, fifo.duration_total);
+    }
+
+    #[test]
+    fn proportional_shards_balance_heterogeneous_nodes() {
+        let profiles = vec![
+            HardwareProfile::new("avx512", Nanoseconds(0.1), 16),
+            HardwareProfile::new("avx2", Nanoseconds(0.17), 16),
+            HardwareProfile::new("arm", Nanoseconds(0.3), 8),
+        ];
+        let build = |shards: &[usize]| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, shards, 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_threads(16, Microseconds(10.))
+                .with_node_profiles(profiles.clone())
+                .build()
+                .simulate_find(0)
+        };
+
+        let equal = build(&[1_000_000; 3]);
+        let proportional = build(&proportional_shard_sizes(3_000_000, &profiles));
+        assert!(proportional.duration < equal.duration);
+    }
+
+    #[test]
+    fn two_level_tree_beats_flat_fan_out() {
+        let build = |tree: AggregationTree| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, &[500_000; 48], 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_scatter_gather_cost(Microseconds(200.), Microseconds(200.))
+                .with_aggregation_tree(tree)
+                .build()
+        };
+
+        let flat = build(AggregationTree::flat()).simulate_find(0);
+        let tree = build(AggregationTree::balanced(48, 2, Microseconds(20.))).simulate_find(0);
+        assert!(tree.duration < flat.duration);
+        assert!(tree.duration_total > flat.duration_total);
+    }
 }
@@ -0,0 +1,55 @@
+use crate::timing::Seconds;
+
+/// Summary statistics over a set of observed latencies.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub mean: Seconds,
+    pub p50: Seconds,
+    pub p95: Seconds,
+    pub p99: Seconds,
+    pub max: Seconds,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &[Seconds]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted: Vec<f64> = samples.iter().map(|s| s.0).collect();
+        sorted.sort_by(f64::total_cmp);
+
+        Self {
+            count: sorted.len(),
+            mean: Seconds(sorted.iter().sum::<f64>() / sorted.len() as f64),
+            p50: Seconds(percentile(&sorted, 0.50)),
+            p95: Seconds(percentile(&sorted, 0.95)),
+            p99: Seconds(percentile(&sorted, 0.99)),
+            max: Seconds(sorted[sorted.len() - 1]),
+        }
+    }
+}
+
+/// Returns the nearest-rank percentile `p` (in `0..=1`) of an ascending slice.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    assert!(!sorted.is_empty());
+    let rank = (p.clamp(0., 1.) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1).min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_work() {
+        let samples: Vec<Seconds> = (1..=100).map(|i| Seconds(i as f64)).collect();
+        let stats = LatencyStats::from_samples(&samples);
+        assert_eq!(stats.count, 100);
+        assert_eq!(*stats.mean, 50.5);
+        assert_eq!(*stats.p50, 50.);
+        assert_eq!(*stats.p99, 99.);
+        assert_eq!(*stats.max, 100.);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::faults::{FaultModel, NodeOutages, ShardOutcome};
+use crate::index::ShardId;
+use crate::placement::Replica;
+use crate::simulation::Simulation;
+use crate::stats::{percentile, LatencyStats};
+use crate::timing::Seconds;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// A technique to keep slow shards from dominating the query latency.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum TailStrategy {
+    /// Wait for every shard, up to the fault model's timeout.
+    #[default]
+    None,
+    /// Send a second request to a replica if the first has not answered after the delay.
+    /// Whichever request finishes last is cancelled.
+    Hedged { delay: HedgeDelay },
+    /// Send the request to two replicas at once. When one finishes, the other is
+    /// cancelled, which takes `cancellation_delay` to reach it.
+    Tied { cancellation_delay: Seconds },
+    /// Return the results of all shards that answered in time for the query to finish
+    /// before the deadline. The scatter-gather overhead counts against the deadline, so
+    /// the shards only get what is left of it.
+    Deadline { deadline: Seconds },
+}
+
+/// How long a hedged request waits before sending the backup request.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HedgeDelay {
+    Fixed(Seconds),
+    /// The given percentile (in `0..=1`) of the observed shard response times.
+    Percentile(f64),
+}
+
+#[derive(Debug)]
+pub struct TailReport {
+    pub latency: LatencyStats,
+    /// The additional shard search time spent, relative to sending one request per shard.
+    /// This is negative if cancelled stragglers save more work than backup requests cost.
+    pub extra_load: f64,
+    /// The number of additional requests sent per shard request.
+    pub extra_requests: f64,
+    /// The average fraction of shards whose results made it into the response.
+    pub completeness: f64,
+}
+
+/// A shard visited by the query, along with the node of its primary copy and the time
+/// it takes to answer without faults.
+type RoutedShard = (ShardId, usize, Seconds);
+
+impl Simulation {
+    /// Runs `num_queries` queries under the given fault model and reports the latency,
+    /// completeness and extra load of a tail-tolerance strategy.
+    ///
+    /// As in [`Simulation::simulate_find_with_faults`], queries are issued every
+    /// `faults.query_interval` to the shards selected by the routing, and node outages
+    /// take down every request their node serves. The first request of a shard goes to
+    /// its primary copy and a backup request to its next replica.
+    pub fn simulate_tail_tolerance(
+        &self,
+        index_id: usize,
+        faults: &FaultModel,
+        strategy: TailStrategy,
+        num_queries: usize,
+        seed: u64,
+    ) -> TailReport {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let index = self.index(index_id);
+        let shard_ids = self.routing.select_shards(index, None);
+        let completion_times = self.shard_completion_times(index, &shard_ids);
+        let shards: Vec<RoutedShard> = shard_ids
+            .iter()
+            .zip(completion_times)
+            .map(|(&shard_id, time)| {
+                let shard = index.shard(shard_id).unwrap();
+                (shard_id, self.node_of(&shard), time)
+            })
+            .collect();
+        let (overhead, _) = self.scatter_gather_overhead(shards.len(), index.vector_length);
+        let timeout = *faults.timeout;
+
+        // The time until a request to a replica answers, or infinity if it never does.
+        let response_time = |rng: &mut StdRng, down: &[bool], shard: &RoutedShard, replica| {
+            let (shard_id, primary, base) = *shard;
+            let replica = Replica {
+                index_id,
+                shard_id,
+                replica,
+            };
+            if down[self.node_of_replica(faults, replica, primary)] {
+                return f64::INFINITY;
+            }
+            match faults.sample_request(rng, shard_id, base) {
+                ShardOutcome::Responded(time) => *time,
+                ShardOutcome::Lost => f64::INFINITY,
+            }
+        };
+
+        let mut outages = NodeOutages::new(&mut rng, faults, self.num_nodes);
+        let mut down = Vec::with_capacity(num_queries);
+        let mut primaries: Vec<Vec<f64>> = Vec::with_capacity(num_queries);
+        for query in 0..num_queries {
+            let time = *faults.query_interval * query as f64;
+            let nodes = outages.down_at(&mut rng, faults, time);
+            primaries.push(
+                shards
+                    .iter()
+                    .map(|shard| response_time(&mut rng, &nodes, shard, 0))
+                    .collect(),
+            );
+            down.push(nodes);
+        }
+
+        let hedge_delay = match strategy {
+            TailStrategy::Hedged {
+                delay: HedgeDelay::Fixed(delay),
+            } => *delay,
+            TailStrategy::Hedged {
+                delay: HedgeDelay::Percentile(p),
+            } => {
+                let mut observed: Vec<f64> = primaries
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .filter(|t| t.is_finite())
+                    .collect();
+                observed.sort_by(f64::total_cmp);
+                if observed.is_empty() {
+                    timeout
+                } else {
+                    percentile(&observed, p)
+                }
+            }
+            _ => f64::INFINITY,
+        };
+
+        let mut latencies = Vec::with_capacity(num_queries);
+        let mut baseline_work = 0.;
+        let mut work = 0.;
+        let mut extra_requests = 0;
+        let mut answered = 0;
+        let cutoff = match strategy {
+            TailStrategy::Deadline { deadline } => (*deadline - *overhead).clamp(0., timeout),
+            _ => timeout,
+        };
+        for (primary, down) in primaries.into_iter().zip(down) {
+            let mut slowest = 0f64;
+            for (shard, first) in shards.iter().zip(primary) {
+                baseline_work += first.min(timeout);
+                let (finished, spent) = match strategy {
+                    TailStrategy::None => (first, first.min(timeout)),
+                    TailStrategy::Deadline { .. } => (first, first.min(cutoff)),
+                    TailStrategy::Hedged { .. } if first <= hedge_delay => (first, first),
+                    TailStrategy::Hedged { .. } => {
+                        extra_requests += 1;
+                        let second = hedge_delay + response_time(&mut rng, &down, shard, 1);
+                        let finished = first.min(second).min(timeout);
+                        (finished, finished + (finished - hedge_delay))
+                    }
+                    TailStrategy::Tied { cancellation_delay } => {
+                        extra_requests += 1;
+                        let second = response_time(&mut rng, &down, shard, 1);
+                        let finished = first.min(second);
+                        let loser = first.max(second).min(finished + *cancellation_delay);
+                        (finished, finished.min(timeout) + loser.min(timeout))
+                    }
+                };
+                work += spent;
+
+                if finished <= cutoff {
+                    answered += 1;
+                    slowest = slowest.max(finished);
+                } else {
+                    slowest = slowest.max(cutoff);
+                }
+            }
+            latencies.push(Seconds(slowest) + overhead);
+        }
+
+        let requests = (num_queries * shards.len()).max(1) as f64;
+        TailReport {
+            latency: LatencyStats::from_samples(&latencies),
+            extra_load: if baseline_work > 0. {
+                work / baseline_work - 1.
+            } else {
+                0.
+            },
+            extra_requests: extra_requests as f64 / requests,
+            completeness: answered as f64 / requests,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 20], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(0.1), Milliseconds(0.1))
+            .build()
+    }
+
+    fn faults() -> FaultModel {
+        FaultModel::new(Seconds(10.)).with_stragglers(0.02, 20.)
+    }
+
+    #[test]
+    fn hedging_cuts_the_tail() {
+        let simulation = simulation();
+        let none = simulation.simulate_tail_tolerance(0, &faults(), TailStrategy::None, 500, 7);
+        let hedged = simulation.simulate_tail_tolerance(
+            0,
+            &faults(),
+            TailStrategy::Hedged {
+                delay: HedgeDelay::Percentile(0.95),
+            },
+            500,
+            7,
+        );
+
+        assert!(hedged.latency.p99 < none.latency.p99);
+        assert!(hedged.extra_requests > 0. && hedged.extra_requests < 0.1);
+        // Cancelling the straggler saves more work than the backup request costs.
+        assert!(hedged.extra_load < 0.);
+        assert_eq!(none.extra_load, 0.);
+        assert_eq!(hedged.completeness, 1.);
+    }
+
+    #[test]
+    fn tied_requests_double_the_requests() {
+        let simulation = simulation();
+        let tied = simulation.simulate_tail_tolerance(
+            0,
+            &faults(),
+            TailStrategy::Tied {
+                cancellation_delay: Milliseconds(1.).into(),
+            },
+            200,
+            7,
+        );
+        assert_eq!(tied.extra_requests, 1.);
+        assert!(tied.extra_load > 0.3);
+        assert!(tied.latency.p99 < simulation.simulate_find(0).duration * 2);
+    }
+
+    #[test]
+    fn deadline_trades_completeness_for_latency() {
+        let simulation = simulation();
+        let deadline = simulation.simulate_find(0).duration;
+        let report = simulation.simulate_tail_tolerance(
+            0,
+            &faults(),
+            TailStrategy::Deadline { deadline },
+            500,
+            7,
+        );
+        assert!(report.completeness < 1.);
+        assert!(*report.latency.max <= *deadline + 1e-12);
+    }
+
+    #[test]
+    fn hedging_routes_around_node_outages() {
+        let simulation = SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[1_000_000; 20], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_nodes(2)
+            .build();
+        let faults = FaultModel::new(Seconds(1.)).with_node_failures(Seconds(90.), Seconds(10.));
+        let none = simulation.simulate_tail_tolerance(0, &faults, TailStrategy::None, 2000, 7);
+        let hedged = simulation.simulate_tail_tolerance(
+            0,
+            &faults,
+            TailStrategy::Hedged {
+                delay: HedgeDelay::Fixed(Milliseconds(500.).into()),
+            },
+            2000,
+            7,
+        );
+
+        // Outages take down half of the shards of a query at once.
+        assert!(none.completeness > 0.8 && none.completeness < 0.95);
+        assert!(hedged.completeness > 0.99);
+    }
+}
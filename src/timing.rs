@@ -45,9 +45,9 @@ impl Deref for Nanoseconds {
     }
 }
 
-impl Into<Seconds> for Milliseconds {
-    fn into(self) -> Seconds {
-        Seconds(self.0 * 1e-3)
+impl From<Milliseconds> for Seconds {
+    fn from(value: Milliseconds) -> Self {
+        Seconds(value.0 * 1e-3)
     }
 }
 
@@ -57,9 +57,9 @@ impl From<Seconds> for Milliseconds {
     }
 }
 
-impl Into<Seconds> for Microseconds {
-    fn into(self) -> Seconds {
-        Seconds(self.0 * 1e-6)
+impl From<Microseconds> for Seconds {
+    fn from(value: Microseconds) -> Self {
+        Seconds(value.0 * 1e-6)
     }
 }
 
@@ -69,9 +69,9 @@ impl From<Seconds> for Microseconds {
     }
 }
 
-impl Into<Seconds> for Nanoseconds {
-    fn into(self) -> Seconds {
-        Seconds(self.0 * 1e-9)
+impl From<Nanoseconds> for Seconds {
+    fn from(value: Nanoseconds) -> Self {
+        Seconds(value.0 * 1e-9)
     }
 }
 
@@ -0,0 +1,169 @@
+use crate::timing::Seconds;
+
+/// Describes how results travel from the shards back to the coordinator.
+///
+/// A flat tree has the coordinator scatter to and gather from every shard directly.
+/// Each additional level inserts aggregators that fan out to at most `fan_out` children
+/// and merge their results before passing them on.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AggregationTree {
+    /// The fan-out of each aggregator level, from the shards upwards.
+    pub fan_out: Vec<usize>,
+    /// The cost for an intermediate aggregator to merge the results of one child.
+    pub merge_cost_per_child: Seconds,
+}
+
+/// A single level of an [`AggregationTree`] laid out over a concrete number of shards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TreeLevel {
+    /// The number of children of the busiest node on this level.
+    pub max_children: usize,
+    /// The number of children of all nodes on this level combined.
+    pub total_children: usize,
+    /// Whether the nodes on this level are intermediate aggregators rather than the coordinator.
+    pub is_aggregator: bool,
+}
+
+/// Describes how a node issues its scatter RPCs to its children.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ScatterModel {
+    /// Each request is sent only after the previous one completed.
+    #[default]
+    Sequential,
+    /// All requests are in flight at the same time.
+    Parallel,
+    /// At most `max_in_flight` requests are outstanding at any time.
+    Bounded { max_in_flight: usize },
+}
+
+impl ScatterModel {
+    /// The time it takes to complete the given requests, each taking its listed time
+    /// once on the wire, plus `serialization` of the sender's time per request to encode.
+    pub fn duration(&self, requests: &[Seconds], serialization: Seconds) -> Seconds {
+        let encoding = serialization * requests.len();
+        let slowest =
+            |requests: &[Seconds]| Seconds(requests.iter().map(|r| r.0).fold(0., f64::max));
+
+        match *self {
+            ScatterModel::Sequential => {
+                Seconds(requests.iter().map(|r| r.0).sum::<f64>()) + encoding
+            }
+            ScatterModel::Parallel => slowest(requests) + encoding,
+            ScatterModel::Bounded { max_in_flight } => {
+                let mut duration = encoding;
+                for wave in requests.chunks(max_in_flight) {
+                    duration += slowest(wave);
+                }
+                duration
+            }
+        }
+    }
+}
+
+impl AggregationTree {
+    /// The coordinator scatters to every shard directly.
+    pub fn flat() -> Self {
+        Self::default()
+    }
+
+    pub fn new<M>(fan_out: Vec<usize>, merge_cost_per_child: M) -> Self
+    where
+        M: Into<Seconds>,
+    {
+        assert!(fan_out.iter().all(|&f| f > 1));
+        Self {
+            fan_out,
+            merge_cost_per_child: merge_cost_per_child.into(),
+        }
+    }
+
+    /// Builds a tree of the given depth whose levels share the same fan-out, chosen such
+    /// that the coordinator has no more children than any aggregator.
+    pub fn balanced<M>(num_shards: usize, depth: usize, merge_cost_per_child: M) -> Self
+    where
+        M: Into<Seconds>,
+    {
+        assert_ne!(depth, 0);
+        let fan_out = (num_shards as f64).powf(1. / depth as f64).ceil() as usize;
+        if depth == 1 || fan_out < 2 {
+            return Self {
+                fan_out: Vec::new(),
+                merge_cost_per_child: merge_cost_per_child.into(),
+            };
+        }
+        Self::new(vec![fan_out; depth - 1], merge_cost_per_child)
+    }
+
+    /// The number of scatter-gather hops between the coordinator and a shard.
+    pub fn depth(&self) -> usize {
+        self.fan_out.len() + 1
+    }
+
+    /// Lays the tree out over `num_shards` shards, from the shards up to the coordinator.
+    pub fn levels(&self, num_shards: usize) -> Vec<TreeLevel> {
+        let mut levels = Vec::with_capacity(self.depth());
+        let mut children = num_shards;
+        for &fan_out in &self.fan_out {
+            if children <= 1 {
+                break;
+            }
+            levels.push(TreeLevel {
+                max_children: fan_out.min(children),
+                total_children: children,
+                is_aggregator: true,
+            });
+            children = children.div_ceil(fan_out);
+        }
+
+        levels.push(TreeLevel {
+            max_children: children,
+            total_children: children,
+            is_aggregator: false,
+        });
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Microseconds;
+
+    #[test]
+    fn flat_tree_has_single_level() {
+        let levels = AggregationTree::flat().levels(40);
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].max_children, 40);
+        assert!(!levels[0].is_aggregator);
+    }
+
+    #[test]
+    fn two_level_tree_works() {
+        let tree = AggregationTree::new(vec![8], Microseconds(10.));
+        let levels = tree.levels(40);
+        assert_eq!(tree.depth(), 2);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].max_children, 8);
+        assert_eq!(levels[0].total_children, 40);
+        assert_eq!(levels[1].max_children, 5);
+    }
+
+    #[test]
+    fn scatter_models_work() {
+        let rpcs = [Microseconds(100.).into(); 10];
+        let encode = Microseconds(1.).into();
+        let sequential = ScatterModel::Sequential.duration(&rpcs, encode);
+        let bounded = ScatterModel::Bounded { max_in_flight: 4 }.duration(&rpcs, encode);
+        let parallel = ScatterModel::Parallel.duration(&rpcs, encode);
+        assert!((*sequential - 1010e-6).abs() < 1e-12);
+        assert!((*bounded - 310e-6).abs() < 1e-12);
+        assert!((*parallel - 110e-6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn balanced_tree_works() {
+        let tree = AggregationTree::balanced(49, 2, Microseconds(10.));
+        assert_eq!(tree.fan_out, vec![7]);
+        assert_eq!(tree.levels(49)[1].max_children, 7);
+    }
+}
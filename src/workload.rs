@@ -0,0 +1,287 @@
+use crate::filter::{FilterPlan, Selectivity};
+use crate::simulation::Simulation;
+use crate::stats::LatencyStats;
+use crate::timing::Seconds;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+
+/// The query rate a single tenant issues against its index.
+#[derive(Debug, Copy, Clone)]
+pub struct TenantLoad {
+    pub index_id: usize,
+    pub queries_per_second: f64,
+    /// The selectivity of the metadata filter each query carries, if any.
+    pub selectivity: Option<Selectivity>,
+}
+
+/// A mix of tenants issuing Poisson-distributed queries against a shared cluster.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub tenants: Vec<TenantLoad>,
+    /// The simulated wall-clock time over which queries arrive.
+    pub duration: Seconds,
+    pub seed: u64,
+}
+
+impl Workload {
+    pub fn new<D>(duration: D, seed: u64) -> Self
+    where
+        D: Into<Seconds>,
+    {
+        Self {
+            tenants: Vec::new(),
+            duration: duration.into(),
+            seed,
+        }
+    }
+
+    pub fn with_tenant(mut self, index_id: usize, queries_per_second: f64) -> Self {
+        assert!(queries_per_second > 0.);
+        self.tenants.push(TenantLoad {
+            index_id,
+            queries_per_second,
+            selectivity: None,
+        });
+        self
+    }
+
+    /// Adds a tenant whose queries carry metadata filters of the given selectivity.
+    pub fn with_filtered_tenant(
+        mut self,
+        index_id: usize,
+        queries_per_second: f64,
+        selectivity: Selectivity,
+    ) -> Self {
+        selectivity.validate();
+        self = self.with_tenant(index_id, queries_per_second);
+        self.tenants.last_mut().unwrap().selectivity = Some(selectivity);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct TenantReport {
+    pub index_id: usize,
+    pub offered_qps: f64,
+    /// Queries completed per second of simulated time.
+    pub throughput_qps: f64,
+    /// The latency of a query from arrival to completion, including queueing.
+    pub latency: LatencyStats,
+    /// The average time a query waited for the busiest of its nodes to become free.
+    pub queueing_delay: Seconds,
+}
+
+#[derive(Debug)]
+pub struct WorkloadReport {
+    pub tenants: Vec<TenantReport>,
+    /// The fraction of time the shared nodes were busy.
+    pub utilization: f64,
+}
+
+impl WorkloadReport {
+    /// The report of the first tenant querying the given index.
+    pub fn tenant(&self, index_id: usize) -> Option<&TenantReport> {
+        self.tenants.iter().find(|t| t.index_id == index_id)
+    }
+}
+
+/// The time a query occupies each node hosting one of its shards, ordered by node.
+pub(crate) type NodeWork = Vec<(usize, f64)>;
+
+struct Arrival {
+    time: f64,
+    /// The position of the tenant in [`Workload::tenants`].
+    tenant: usize,
+    work: NodeWork,
+    overhead: f64,
+}
+
+impl Simulation {
+    /// The time a query executed with the given filter plan occupies each node hosting
+    /// one of its routed shards, along with its scatter-gather overhead. The shards a
+    /// query has on the same node are scheduled together by the node's pool.
+    pub(crate) fn node_work(&self, index_id: usize, filter: &FilterPlan) -> (NodeWork, f64) {
+        let index = self.index(index_id);
+        let shard_ids = self.routing.select_shards(index, None);
+        let completion_times = self.filtered_completion_times(index, &shard_ids, filter);
+        let mut work = BTreeMap::new();
+        for (&shard_id, time) in shard_ids.iter().zip(completion_times) {
+            let node = self.node_of(&index.shard(shard_id).unwrap());
+            let busy: &mut f64 = work.entry(node).or_default();
+            *busy = busy.max(*time);
+        }
+        let (overhead, _) = self.scatter_gather_overhead(shard_ids.len(), index.vector_length);
+        (work.into_iter().collect(), *overhead)
+    }
+
+    /// Replays a multi-tenant workload against the shared nodes of the simulation.
+    ///
+    /// Every query sends its shard requests to the nodes hosting them, see
+    /// [`Simulation::node_of`], where they take as long as in
+    /// [`Simulation::simulate_find`], or in [`Simulation::simulate_find_filtered`] if the
+    /// tenant filters. Each node serves the requests of all tenants from one FIFO queue,
+    /// so tenants whose shards share nodes with an expensive tenant are delayed by it.
+    pub fn simulate_workload(&self, workload: &Workload) -> WorkloadReport {
+        let mut rng = StdRng::seed_from_u64(workload.seed);
+        let horizon = *workload.duration;
+
+        let mut arrivals = Vec::new();
+        let mut unfiltered_work = HashMap::new();
+        for (position, tenant) in workload.tenants.iter().enumerate() {
+            let mut time = 0f64;
+            loop {
+                time += -(1. - rng.gen::<f64>()).ln() / tenant.queries_per_second;
+                if time >= horizon {
+                    break;
+                }
+                let (work, overhead) = match tenant.selectivity {
+                    Some(selectivity) => {
+                        let index = self.index(tenant.index_id);
+                        let plan = index.filter_strategy.plan(selectivity.sample(&mut rng));
+                        self.node_work(tenant.index_id, &plan)
+                    }
+                    None => unfiltered_work
+                        .entry(tenant.index_id)
+                        .or_insert_with(|| {
+                            self.node_work(tenant.index_id, &FilterPlan::unfiltered())
+                        })
+                        .clone(),
+                };
+                arrivals.push(Arrival {
+                    time,
+                    tenant: position,
+                    work,
+                    overhead,
+                });
+            }
+        }
+        arrivals.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut node_free_at = vec![0f64; self.num_nodes];
+        let mut busy_time = 0f64;
+        // Tenants sharing an index are reported separately.
+        let mut latencies: Vec<Vec<Seconds>> = vec![Vec::new(); workload.tenants.len()];
+        let mut waits = vec![0f64; workload.tenants.len()];
+        let mut completed = vec![0usize; workload.tenants.len()];
+
+        for arrival in arrivals {
+            let mut finish = arrival.time;
+            let mut wait = 0f64;
+            for &(node, service) in &arrival.work {
+                let start = node_free_at[node].max(arrival.time);
+                node_free_at[node] = start + service;
+                busy_time += service;
+                finish = finish.max(start + service);
+                wait = wait.max(start - arrival.time);
+            }
+            let finish = finish + arrival.overhead;
+
+            latencies[arrival.tenant].push(Seconds(finish - arrival.time));
+            waits[arrival.tenant] += wait;
+            if finish <= horizon {
+                completed[arrival.tenant] += 1;
+            }
+        }
+
+        let tenants = workload
+            .tenants
+            .iter()
+            .zip(latencies)
+            .enumerate()
+            .map(|(position, (tenant, samples))| {
+                let count = samples.len().max(1) as f64;
+                TenantReport {
+                    index_id: tenant.index_id,
+                    offered_qps: tenant.queries_per_second,
+                    throughput_qps: completed[position] as f64 / horizon,
+                    latency: LatencyStats::from_samples(&samples),
+                    queueing_delay: Seconds(waits[position] / count),
+                }
+            })
+            .collect();
+
+        WorkloadReport {
+            tenants,
+            utilization: (busy_time / (horizon * self.num_nodes as f64)).min(1.),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::simulation::SimulationBuilder;
+    use crate::timing::{Milliseconds, Nanoseconds};
+
+    fn simulation() -> Simulation {
+        SimulationBuilder::default()
+            .with_index(Index::new_from_shards(0, &[100_000], 768))
+            .with_index(Index::new_from_shards(1, &[2_000_000; 2], 768))
+            .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+            .with_scatter_gather_cost(Milliseconds(1.), Milliseconds(1.))
+            .with_nodes(2)
+            .build()
+    }
+
+    #[test]
+    fn light_load_keeps_up() {
+        let simulation = simulation();
+        let workload = Workload::new(Seconds(60.), 42).with_tenant(0, 10.);
+        let report = simulation.simulate_workload(&workload);
+        let tenant = report.tenant(0).unwrap();
+        assert!((tenant.throughput_qps - 10.).abs() < 1.);
+        assert!(report.utilization < 0.1);
+    }
+
+    #[test]
+    fn noisy_neighbour_inflates_latency() {
+        let simulation = simulation();
+        let alone = Workload::new(Seconds(60.), 42).with_tenant(0, 10.);
+        let shared = alone.clone().with_tenant(1, 5.);
+
+        let alone = simulation.simulate_workload(&alone);
+        let shared = simulation.simulate_workload(&shared);
+        assert!(shared.tenant(0).unwrap().latency.p99 > alone.tenant(0).unwrap().latency.p99);
+        assert!(shared.utilization > alone.utilization);
+    }
+
+    #[test]
+    fn tenants_sharing_an_index_are_reported_separately() {
+        let workload = Workload::new(Seconds(60.), 42)
+            .with_tenant(0, 10.)
+            .with_tenant(0, 40.);
+        let report = simulation().simulate_workload(&workload);
+        let (low, high) = (&report.tenants[0], &report.tenants[1]);
+        assert!((low.throughput_qps - 10.).abs() < 2.);
+        assert!((high.throughput_qps - 40.).abs() < 4.);
+        assert!(low.latency.count > 0 && high.latency.count > 3 * low.latency.count);
+    }
+
+    #[test]
+    fn interference_follows_shard_placement() {
+        // The large shard of index 1 either shares node 0 with index 0 or sits alone on
+        // node 1 next to an empty shard.
+        let build = |shards: &[usize]| {
+            SimulationBuilder::default()
+                .with_index(Index::new_from_shards(0, &[100_000], 768))
+                .with_index(Index::new_from_shards(1, shards, 768))
+                .with_search_cost(Nanoseconds(0.171326754), Nanoseconds(10.))
+                .with_nodes(2)
+                .build()
+        };
+        let workload = Workload::new(Seconds(60.), 42)
+            .with_tenant(0, 10.)
+            .with_tenant(1, 5.);
+
+        let alone = build(&[0, 2_000_000]).simulate_workload(&workload);
+        let shared = build(&[2_000_000, 0]).simulate_workload(&workload);
+        let alone_latency = alone.tenants[0].latency.mean;
+        let isolated = Workload::new(Seconds(60.), 42).with_tenant(0, 10.);
+        let isolated = build(&[0]).simulate_workload(&isolated);
+        assert!((*alone_latency - *isolated.tenants[0].latency.mean).abs() < 1e-9);
+        assert!(shared.tenants[0].latency.mean > alone_latency * 2);
+        assert!((alone.utilization - shared.utilization).abs() < 1e-9);
+    }
+}